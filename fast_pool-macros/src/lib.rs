@@ -5,7 +5,8 @@ use proc_macro2::TokenStream as TokenStream2;
 use syn::{Result, ItemFn, AttributeArgs, parse2, parse_macro_input};
 use darling::FromMeta;
 
-/// Creates and initializes a new thread pool.
+/// Creates and initializes a new thread pool, which is entered with `Handle::enter` for the
+/// whole body of the function.
 ///
 /// This macro accepts several inputs to modify the thread pool.
 ///
@@ -67,10 +68,11 @@ fn _init(args: AttributeArgs, input: TokenStream2) -> Result<TokenStream2> {
     let options = options::PoolOptions::from_list(&args).unwrap();
 
     let builder = quote::quote! {
-        fast_pool::ThreadPoolBuilder::new()
+        let _fast_pool_guard = fast_pool::ThreadPoolBuilder::new()
             #options
             .build()
-            .expect("Failed to build thread pool");
+            .expect("Failed to build thread pool")
+            .enter();
     };
 
     let block = &fun.block;
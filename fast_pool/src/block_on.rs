@@ -0,0 +1,65 @@
+use crate::shared::Shared;
+use crossbeam_utils::sync::{Parker, Unparker};
+use std::future::Future;
+use std::pin::pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+use std::time::Duration;
+
+/// How long a worker parks when there are no tasks to run while waiting for a future, after
+/// that it looks again for tasks spawned in the meantime.
+const HELP_INTERVAL: Duration = Duration::from_millis(1);
+
+/// The waker used to unpark the thread blocked on a future.
+struct ParkWaker {
+    unparker: Unparker,
+    notified: AtomicBool,
+}
+
+impl Wake for ParkWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref()
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.notified.store(true, Ordering::Release);
+        self.unparker.unpark();
+    }
+}
+
+/// Runs the future to completion on the current thread. If the current thread is a worker of
+/// the given pool, it keeps running queued tasks until the future is woken instead of parking,
+/// so the pool doesn't run out of threads if the future waits for other tasks.
+pub fn block_on<F: Future>(shared: Option<&Shared>, future: F) -> F::Output {
+    let parker = Parker::new();
+    let park_waker = Arc::new(ParkWaker {
+        unparker: parker.unparker().clone(),
+        notified: AtomicBool::new(false),
+    });
+    let waker = Waker::from(Arc::clone(&park_waker));
+    let mut cx = Context::from_waker(&waker);
+    let mut future = pin!(future);
+    let worker = shared.filter(|shared| crate::worker::is_worker_of(shared));
+
+    loop {
+        if let Poll::Ready(value) = future.as_mut().poll(&mut cx) {
+            return value;
+        }
+
+        match worker {
+            Some(shared) => {
+                while !park_waker.notified.swap(false, Ordering::AcqRel) {
+                    if !crate::worker::run_pending(shared) {
+                        parker.park_timeout(HELP_INTERVAL);
+                    }
+                }
+            }
+            None => {
+                while !park_waker.notified.swap(false, Ordering::AcqRel) {
+                    parker.park();
+                }
+            }
+        }
+    }
+}
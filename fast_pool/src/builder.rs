@@ -1,18 +1,68 @@
+use crate::hook::{TaskInfo, WorkerContext};
+use crate::panic::TaskPanic;
 use crate::threadpool::ThreadPool;
 use std::sync::Arc;
+use std::time::Duration;
 
-pub(crate) type HookFn = dyn Fn() + Send + Sync + 'static;
+pub(crate) type HookFn = dyn Fn(&WorkerContext<'_>) + Send + Sync + 'static;
+pub(crate) type TaskHookFn = dyn Fn(&WorkerContext<'_>, &TaskInfo) + Send + Sync + 'static;
 pub(crate) type NameFn = dyn Fn() -> String + Send + Sync + 'static;
+pub(crate) type PanicFn = dyn Fn(&TaskPanic<'_>) + Send + Sync + 'static;
+
+/// The policy followed when spawning a task into a pool whose queue is full, see
+/// [queue_capacity](ThreadPoolBuilder::queue_capacity).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RejectionPolicy {
+    /// Blocks the spawning thread until there is space in the queue. When spawning from a worker
+    /// of the same pool, the task runs on the spawning thread instead, as blocking a worker
+    /// could leave the pool without threads to make room in the queue.
+    Block,
+    /// Returns a [queue full](crate::SpawnError::QueueFull) error, panicking when spawning with
+    /// the non fallible methods.
+    Error,
+    /// Runs the task on the spawning thread.
+    CallerRuns,
+    /// Drops the oldest task in the queue to make room for the new one, waiting for the dropped
+    /// task will return a [rejected](crate::JoinError::is_rejected) error.
+    DropOldest,
+    /// Drops the new task, waiting for it will return a [rejected](crate::JoinError::is_rejected)
+    /// error. The fallible spawn methods give the task back instead.
+    DropNew,
+}
+
+/// The policy followed when a task panics, after calling the
+/// [panic handler](ThreadPoolBuilder::panic_handler), see
+/// [panic_policy](ThreadPoolBuilder::panic_policy).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PanicPolicy {
+    /// Keeps the pool running, the panic is only seen through the handle of the task, if any.
+    #[default]
+    Continue,
+    /// Aborts the process.
+    Abort,
+    /// Shuts the pool down, no more tasks are accepted and the queued ones are discarded like in
+    /// [shutdown](crate::Handle::shutdown), the tasks already running are allowed to finish.
+    Shutdown,
+}
 
 /// A builder which allows to configure the thread pool before building it.
 pub struct ThreadPoolBuilder {
     pub(crate) on_start: Option<Arc<HookFn>>,
     pub(crate) on_stop: Option<Arc<HookFn>>,
-    pub(crate) before: Option<Arc<HookFn>>,
-    pub(crate) after: Option<Arc<HookFn>>,
+    pub(crate) before: Option<Arc<TaskHookFn>>,
+    pub(crate) after: Option<Arc<TaskHookFn>>,
     pub(crate) name: Arc<NameFn>,
+    pub(crate) pool_name: String,
     pub(crate) thread_number: usize,
+    pub(crate) min_threads: Option<usize>,
+    pub(crate) max_threads: Option<usize>,
+    pub(crate) keep_alive: Duration,
     pub(crate) stack_size: Option<usize>,
+    pub(crate) queue_capacity: Option<usize>,
+    pub(crate) rejection_policy: RejectionPolicy,
+    pub(crate) panic_handler: Option<Arc<PanicFn>>,
+    pub(crate) panic_policy: PanicPolicy,
+    pub(crate) timer: bool,
 }
 
 impl ThreadPoolBuilder {
@@ -24,33 +74,69 @@ impl ThreadPoolBuilder {
             before: None,
             after: None,
             name: Arc::new(|| String::from("fast_pool-worker")),
+            pool_name: String::from("fast_pool"),
             thread_number: num_cpus::get() * 2,
+            min_threads: None,
+            max_threads: None,
+            keep_alive: Duration::from_secs(60),
             stack_size: None,
+            queue_capacity: None,
+            rejection_policy: RejectionPolicy::Block,
+            panic_handler: None,
+            panic_policy: PanicPolicy::Continue,
+            timer: true,
         }
     }
 
     /// Sets a function to execute before every task.
-    pub fn before<F>(mut self, fun: F) -> Self
+    pub fn before<F>(self, fun: F) -> Self
     where
         F: Fn() + Send + Sync + 'static,
+    {
+        self.before_with(move |_, _| fun())
+    }
+
+    /// Like [before](Self::before), but the function receives the worker and the task it runs
+    /// for.
+    pub fn before_with<F>(mut self, fun: F) -> Self
+    where
+        F: Fn(&WorkerContext<'_>, &TaskInfo) + Send + Sync + 'static,
     {
         self.before = Some(Arc::new(fun));
         self
     }
 
     /// Sets a function to execute after every task.
-    pub fn after<F>(mut self, fun: F) -> Self
+    pub fn after<F>(self, fun: F) -> Self
     where
         F: Fn() + Send + Sync + 'static,
+    {
+        self.after_with(move |_, _| fun())
+    }
+
+    /// Like [after](Self::after), but the function receives the worker and the task it ran
+    /// for, along with the time it ran for.
+    pub fn after_with<F>(mut self, fun: F) -> Self
+    where
+        F: Fn(&WorkerContext<'_>, &TaskInfo) + Send + Sync + 'static,
     {
         self.after = Some(Arc::new(fun));
         self
     }
 
     /// Sets a function to execute at thread creation, before start doing any work.
-    pub fn on_start<F>(mut self, fun: F) -> Self
+    pub fn on_start<F>(self, fun: F) -> Self
     where
         F: Fn() + Send + Sync + 'static,
+    {
+        self.on_start_with(move |_| fun())
+    }
+
+    /// Like [on_start](Self::on_start), but the function receives the worker being started,
+    /// which allows setting up per worker resources keyed by its index.
+    pub fn on_start_with<F>(mut self, fun: F) -> Self
+    where
+        F: Fn(&WorkerContext<'_>) + Send + Sync + 'static,
     {
         self.on_start = Some(Arc::new(fun));
         self
@@ -58,9 +144,17 @@ impl ThreadPoolBuilder {
 
     /// Sets a function to execute at thread stop, this will mainly be called when the thread pool
     /// shuts down just before exiting the thread.
-    pub fn on_stop<F>(mut self, fun: F) -> Self
+    pub fn on_stop<F>(self, fun: F) -> Self
     where
         F: Fn() + Send + Sync + 'static,
+    {
+        self.on_stop_with(move |_| fun())
+    }
+
+    /// Like [on_stop](Self::on_stop), but the function receives the worker being stopped.
+    pub fn on_stop_with<F>(mut self, fun: F) -> Self
+    where
+        F: Fn(&WorkerContext<'_>) + Send + Sync + 'static,
     {
         self.on_stop = Some(Arc::new(fun));
         self
@@ -82,6 +176,13 @@ impl ThreadPoolBuilder {
         self
     }
 
+    /// Sets the name of the pool, used to tell pools apart in their metrics, by default
+    /// `fast_pool`.
+    pub fn pool_name(mut self, name: impl ToString) -> Self {
+        self.pool_name = name.to_string();
+        self
+    }
+
     /// Sets the stack size for the threads of the pool.
     pub fn thread_stack_size(mut self, size: usize) -> Self {
         self.stack_size = Some(size);
@@ -94,6 +195,71 @@ impl ThreadPoolBuilder {
         self
     }
 
+    /// Sets the minimum number of threads of the pool, which are spawned when the pool starts
+    /// and never retired, by default the [number of threads](Self::thread_number).
+    pub fn min_threads(mut self, threads: usize) -> Self {
+        self.min_threads = Some(threads);
+        self
+    }
+
+    /// Sets the maximum number of threads of the pool, by default the
+    /// [number of threads](Self::thread_number). When there are tasks waiting and every thread
+    /// is busy, new threads are spawned up to this number, which are retired once they stay
+    /// idle for the [keep alive](Self::keep_alive) time.
+    pub fn max_threads(mut self, threads: usize) -> Self {
+        self.max_threads = Some(threads);
+        self
+    }
+
+    /// Sets how long the threads above the [minimum](Self::min_threads) can stay idle before
+    /// being retired, by default 60 seconds.
+    pub fn keep_alive(mut self, keep_alive: Duration) -> Self {
+        self.keep_alive = keep_alive;
+        self
+    }
+
+    /// Sets the maximum number of tasks waiting to run the pool can hold, by default the queue
+    /// is unbounded. What happens when spawning into a full queue is determined by the
+    /// [rejection policy](Self::rejection_policy).
+    pub fn queue_capacity(mut self, capacity: usize) -> Self {
+        self.queue_capacity = Some(capacity);
+        self
+    }
+
+    /// Sets the policy followed when spawning a task into a full queue, by default
+    /// [RejectionPolicy::Block](RejectionPolicy::Block).
+    pub fn rejection_policy(mut self, policy: RejectionPolicy) -> Self {
+        self.rejection_policy = policy;
+        self
+    }
+
+    /// Sets a function to call whenever a task panics, including detached and periodic tasks
+    /// whose panics would go unnoticed otherwise. It runs on the thread which ran the task,
+    /// before the panic is given to the handle of the task.
+    pub fn panic_handler<F>(mut self, fun: F) -> Self
+    where
+        F: Fn(&TaskPanic<'_>) + Send + Sync + 'static,
+    {
+        self.panic_handler = Some(Arc::new(fun));
+        self
+    }
+
+    /// Sets the policy followed when a task panics, by default
+    /// [PanicPolicy::Continue](PanicPolicy::Continue).
+    pub fn panic_policy(mut self, policy: PanicPolicy) -> Self {
+        self.panic_policy = policy;
+        self
+    }
+
+    /// Sets whether the pool has a timer, which runs on its own thread to queue the delayed and
+    /// periodic tasks once they are due, by default enabled. Without it, spawning those tasks
+    /// with [try_spawn_after](crate::Handle::try_spawn_after) and the like fails with a
+    /// [no timer](crate::SpawnError::NoTimer) error, while the panicking variants panic.
+    pub fn timer(mut self, enabled: bool) -> Self {
+        self.timer = enabled;
+        self
+    }
+
     /// Builds into a [ThreadPool](ThreadPool) and starts it.
     pub fn build(self) -> std::io::Result<ThreadPool> {
         ThreadPool::start(self)
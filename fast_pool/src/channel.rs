@@ -1,97 +1,239 @@
+use crate::join::JoinError;
 use crossbeam_utils::sync::{Parker, Unparker};
+use parking_lot::Mutex;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Arc;
 use std::task::{Context, Poll, Waker};
-use std::{cell::UnsafeCell, sync::Arc};
-
-type BoxedError = Box<dyn std::any::Any + Send + 'static>;
+use std::time::Instant;
+
+/// The task is waiting in the queue.
+const IDLE: u8 = 0;
+/// The task is being executed by a worker.
+const RUNNING: u8 = 1;
+/// The task ran and its output was stored.
+const COMPLETE: u8 = 2;
+/// The task was aborted or discarded before it started running.
+const CANCELLED: u8 = 3;
+
+/// Receives the notifications of the channels of the tasks of a [group](crate::TaskGroup).
+pub trait GroupNotify: Send + Sync {
+    /// Notifies that the task with the given id finished.
+    fn notify(&self, id: usize);
+}
 
-enum Notifier {
+pub(crate) enum Notifier {
     Unparker(Unparker),
-    Waker(Waker)
+    Waker(Waker),
+    Group(Arc<dyn GroupNotify>, usize)
 }
 
 impl Notifier {
-    fn notify(self) {
+    pub(crate) fn notify(self) {
         match self {
             Self::Unparker(unparker) => unparker.unpark(),
-            Self::Waker(waker) => waker.wake()
+            Self::Waker(waker) => waker.wake(),
+            Self::Group(group, id) => group.notify(id)
         }
     }
 }
 
-struct ChannelInner<T> {
-    data: Option<Result<T, BoxedError>>,
+struct Slot<T> {
+    data: Option<Result<T, JoinError>>,
     notifier: Option<Notifier>
 }
 
+struct ChannelInner<T> {
+    state: AtomicU8,
+    slot: Mutex<Slot<T>>
+}
+
 impl<T: Send> ChannelInner<T> {
     fn new() -> Self {
         Self {
-            data: None,
-            notifier: None
+            state: AtomicU8::new(IDLE),
+            slot: Mutex::new(Slot {
+                data: None,
+                notifier: None
+            })
+        }
+    }
+
+    fn store(&self, value: Result<T, JoinError>) {
+        let notifier = {
+            let mut slot = self.slot.lock();
+            slot.data = Some(value);
+            slot.notifier.take()
+        };
+
+        // Notify outside of the lock, so the waiting thread can take the value right away.
+        if let Some(notifier) = notifier {
+            notifier.notify();
         }
     }
 }
 
-pub struct ChannelHalf<T> {
-    inner: Arc<UnsafeCell<ChannelInner<T>>>,
+/// A type erased channel which can be cancelled, used by [AbortHandle](crate::AbortHandle).
+pub trait Cancel: Send + Sync {
+    /// Fails the task with the given error if it didn't start running yet, returning whether
+    /// it failed.
+    fn fail(&self, error: JoinError) -> bool;
+
+    /// Cancels the task if it didn't start running yet, returning whether it was cancelled.
+    fn cancel(&self) -> bool {
+        self.fail(JoinError::cancelled())
+    }
 }
 
-unsafe impl<T> Send for ChannelHalf<T> {}
-unsafe impl<T> Sync for ChannelHalf<T> {}
+impl<T: Send> Cancel for ChannelInner<T> {
+    fn fail(&self, error: JoinError) -> bool {
+        let failed = self.state
+            .compare_exchange(IDLE, CANCELLED, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok();
+
+        if failed {
+            self.store(Err(error));
+        }
+
+        failed
+    }
+}
+
+pub struct ChannelHalf<T> {
+    inner: Arc<ChannelInner<T>>,
+}
 
 impl<T: Send + Sized + 'static> ChannelHalf<T> {
-    fn new(cell: Arc<UnsafeCell<ChannelInner<T>>>) -> Self {
-        Self { inner: cell }
+    fn new(inner: Arc<ChannelInner<T>>) -> Self {
+        Self { inner }
     }
 
     pub fn new_pair() -> (Self, Self) {
-        let inner = Arc::new(UnsafeCell::new(ChannelInner::new()));
+        let inner = Arc::new(ChannelInner::new());
         (Self::new(Arc::clone(&inner)), Self::new(inner))
     }
 
-    pub fn set_waker(&self, waker: Waker) {
-        // SAFETY: Only the worker thread which is executing the task uses this method, so there is
-        // no risk of any data races.
-        let inner = unsafe { &mut *self.inner.get() };
-        inner.notifier = Some(Notifier::Waker(waker));
+    /// Marks the task as running, returns false if the task was cancelled, in which case it
+    /// must not run.
+    pub fn start(&self) -> bool {
+        self.inner.state
+            .compare_exchange(IDLE, RUNNING, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+    }
+
+    /// Cancels the task if it didn't start running yet, returning whether it was cancelled.
+    pub fn cancel(&self) -> bool {
+        self.inner.cancel()
     }
 
-    pub fn try_get(&self) -> Option<Result<T, BoxedError>> {
-        // SAFETY: Only one thread is meant to use this method, so there are no race conditions.
-        let inner = unsafe { &mut *self.inner.get() };
-        inner.data.take()
+    /// Returns a type erased reference to the channel, used to cancel it.
+    pub fn cancel_handle(&self) -> Arc<dyn Cancel> {
+        Arc::clone(&self.inner) as Arc<dyn Cancel>
     }
 
-    pub fn set(self, value: Result<T, BoxedError>) {
-        // SAFETY: Only a worker thread is able to use this method.
-        let inner = unsafe { &mut *self.inner.get() };
-        inner.data = Some(value);
+    pub fn try_get(&self) -> Option<Result<T, JoinError>> {
+        self.inner.slot.lock().data.take()
+    }
+
+    /// Fails the task with the given error if it didn't start running yet, see
+    /// [Cancel::fail](Cancel::fail).
+    pub fn fail(&self, error: JoinError) -> bool {
+        self.inner.fail(error)
+    }
 
-        inner.notifier.take().map(Notifier::notify);
+    pub fn set(self, value: Result<T, JoinError>) {
+        self.inner.state.store(COMPLETE, Ordering::Release);
+        self.inner.store(value);
     }
 
-    pub fn wait_async(&mut self, cx: &mut Context) -> Poll<Result<T, BoxedError>> {
-        if let Some(value) = self.try_get() {
+    pub fn wait_async(&mut self, cx: &mut Context) -> Poll<Result<T, JoinError>> {
+        let mut slot = self.inner.slot.lock();
+
+        if let Some(value) = slot.data.take() {
             Poll::Ready(value)
         } else {
-            self.set_waker(cx.waker().clone());
+            slot.notifier = Some(Notifier::Waker(cx.waker().clone()));
             Poll::Pending
         }
     }
 
-    pub fn wait(self) -> Result<T, BoxedError> {
-        if let Some(value) = self.try_get() {
-            value
+    /// Whether the value is already stored.
+    pub fn is_set(&self) -> bool {
+        self.inner.slot.lock().data.is_some()
+    }
+
+    /// Registers an unparker to be notified when the value gets stored, returns the value
+    /// instead if it's already stored.
+    fn register_unparker(&self, parker: &Parker) -> Option<Result<T, JoinError>> {
+        let mut slot = self.inner.slot.lock();
+
+        if let Some(value) = slot.data.take() {
+            return Some(value);
+        }
+
+        slot.notifier = Some(Notifier::Unparker(parker.unparker().clone()));
+        None
+    }
+
+    /// Registers the group to be notified when the value gets stored, the group is notified
+    /// right away if it's already stored.
+    pub fn register_group(&self, group: Arc<dyn GroupNotify>, id: usize) {
+        let mut slot = self.inner.slot.lock();
+
+        if slot.data.is_some() {
+            drop(slot);
+            group.notify(id);
         } else {
-            // SAFETY: This method can only be called once as it consumes `self`.
-            let inner = unsafe { &mut *self.inner.get() };
-            let parker = Parker::new();
-            inner.notifier = Some(Notifier::Unparker(parker.unparker().clone()));
+            slot.notifier = Some(Notifier::Group(group, id));
+        }
+    }
+
+    pub fn wait(self) -> Result<T, JoinError> {
+        let parker = Parker::new();
 
-            // Park the thread so no work is done while waiting.
+        if let Some(value) = self.register_unparker(&parker) {
+            return value;
+        }
+
+        // Park the thread so no work is done while waiting, until the value gets stored.
+        loop {
             parker.park();
-            // Now wait until the thread is unparked.
-            self.try_get().unwrap()
+
+            if let Some(value) = self.try_get() {
+                return value;
+            }
+        }
+    }
+
+    /// Waits until the value gets stored or the deadline is reached, in which case the channel
+    /// is given back.
+    pub fn wait_deadline(self, deadline: Instant) -> Result<Result<T, JoinError>, Self> {
+        let parker = Parker::new();
+
+        if let Some(value) = self.register_unparker(&parker) {
+            return Ok(value);
+        }
+
+        loop {
+            let now = Instant::now();
+
+            if now >= deadline {
+                let mut slot = self.inner.slot.lock();
+
+                return match slot.data.take() {
+                    Some(value) => Ok(value),
+                    None => {
+                        slot.notifier = None;
+                        drop(slot);
+                        Err(self)
+                    }
+                };
+            }
+
+            parker.park_timeout(deadline - now);
+
+            if let Some(value) = self.try_get() {
+                return Ok(value);
+            }
         }
     }
 }
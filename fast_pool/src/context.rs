@@ -1,51 +1,54 @@
 use crate::handle::Handle;
 use std::cell::RefCell;
-use crate::timer::TimerHandle;
+use std::marker::PhantomData;
 
-struct HandleWrapper {
-    pub threadpool: RefCell<Option<Handle>>,
-    pub timer: RefCell<Option<TimerHandle>>
+thread_local! {
+    /// The pool which is current on this thread, workers see their own pool and the rest of
+    /// the threads the one they [entered](Handle::enter), if any.
+    static CURRENT: RefCell<Option<Handle>> = const { RefCell::new(None) };
 }
-unsafe impl Sync for HandleWrapper {}
 
-static HANDLE: HandleWrapper = HandleWrapper {
-    threadpool: RefCell::new(None),
-    timer: RefCell::new(None)
-};
-const NOT_INITIALIZED: &str = "Thread pool not initialized";
+const NOT_INITIALIZED: &str = "Thread pool not initialized, see `Handle::enter`";
 
 pub fn get_handle() -> Handle {
-    try_get().expect(NOT_INITIALIZED).clone()
+    try_get().expect(NOT_INITIALIZED)
 }
 
 pub fn try_get() -> Option<Handle> {
-    HANDLE.threadpool.borrow().clone()
+    CURRENT.with(|current| current.borrow().clone())
 }
 
-pub fn set_handle(handle: Handle) {
-    *HANDLE.threadpool.borrow_mut() = Some(handle);
-}
+/// Makes the given pool the current one of this thread until the returned guard is dropped.
+pub fn enter(handle: Handle) -> EnterGuard {
+    let previous = CURRENT.with(|current| current.replace(Some(handle)));
 
-pub fn delete_handle() {
-    *HANDLE.threadpool.borrow_mut() = None;
+    EnterGuard {
+        previous,
+        _not_send: PhantomData,
+    }
 }
 
-pub fn get_timer() -> TimerHandle {
-    let option = HANDLE.timer.borrow().clone();
-
-    if option.is_none() {
-        *HANDLE.timer.borrow_mut() = Some(TimerHandle::new().expect("Failed to spawn timer"));
-    };
-
-    option.or_else(|| {
-        HANDLE.timer.borrow().clone()
-    }).unwrap()
+/// A guard which keeps a pool as the current one of the thread, returned by
+/// [enter](Handle::enter). Once dropped, the pool which was current before is restored.
+///
+/// Guards should be dropped in the reverse order they were created, they can't be sent to
+/// other threads.
+#[must_use = "the pool stops being the current one once the guard is dropped"]
+pub struct EnterGuard {
+    previous: Option<Handle>,
+    /// The guard restores the context of the thread it was created on.
+    _not_send: PhantomData<*const ()>,
 }
 
-pub fn get_timer_optional() -> Option<TimerHandle> {
-    HANDLE.timer.borrow().clone()
+impl Drop for EnterGuard {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        CURRENT.with(|current| *current.borrow_mut() = previous);
+    }
 }
 
-pub fn delete_timer() {
-    *HANDLE.timer.borrow_mut() = None;
+impl std::fmt::Debug for EnterGuard {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EnterGuard").finish_non_exhaustive()
+    }
 }
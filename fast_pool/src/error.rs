@@ -0,0 +1,81 @@
+use std::fmt;
+
+/// The error returned when a task can't be spawned, it gives back the task so it isn't lost.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SpawnError<T> {
+    /// The thread pool was shut down.
+    Shutdown(T),
+    /// The task queue of the pool is full.
+    QueueFull(T),
+    /// There is no current thread pool, see [Handle::enter](crate::Handle::enter).
+    NoPool(T),
+    /// The [timer](crate::ThreadPoolBuilder::timer) of the pool is disabled, so it can't run
+    /// delayed nor periodic tasks.
+    NoTimer(T),
+}
+
+impl<T> SpawnError<T> {
+    /// Consumes the error, returning the task which couldn't be spawned.
+    pub fn into_inner(self) -> T {
+        match self {
+            Self::Shutdown(task)
+            | Self::QueueFull(task)
+            | Self::NoPool(task)
+            | Self::NoTimer(task) => task,
+        }
+    }
+
+    /// Whether the task couldn't be spawned because the pool was shut down.
+    pub fn is_shutdown(&self) -> bool {
+        matches!(self, Self::Shutdown(_))
+    }
+
+    /// Whether the task couldn't be spawned because the task queue is full.
+    pub fn is_queue_full(&self) -> bool {
+        matches!(self, Self::QueueFull(_))
+    }
+
+    /// Whether the task couldn't be spawned because there is no current pool.
+    pub fn is_no_pool(&self) -> bool {
+        matches!(self, Self::NoPool(_))
+    }
+
+    /// Whether the task couldn't be spawned because the timer of the pool is disabled.
+    pub fn is_no_timer(&self) -> bool {
+        matches!(self, Self::NoTimer(_))
+    }
+
+    /// Replaces the value carried by the error, keeping the reason.
+    pub(crate) fn with<U>(self, value: U) -> SpawnError<U> {
+        match self {
+            Self::Shutdown(_) => SpawnError::Shutdown(value),
+            Self::QueueFull(_) => SpawnError::QueueFull(value),
+            Self::NoPool(_) => SpawnError::NoPool(value),
+            Self::NoTimer(_) => SpawnError::NoTimer(value),
+        }
+    }
+}
+
+impl<T> fmt::Debug for SpawnError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Shutdown(_) => f.write_str("Shutdown(..)"),
+            Self::QueueFull(_) => f.write_str("QueueFull(..)"),
+            Self::NoPool(_) => f.write_str("NoPool(..)"),
+            Self::NoTimer(_) => f.write_str("NoTimer(..)"),
+        }
+    }
+}
+
+impl<T> fmt::Display for SpawnError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Shutdown(_) => f.write_str("thread pool exited"),
+            Self::QueueFull(_) => f.write_str("task queue is full"),
+            Self::NoPool(_) => f.write_str("thread pool not initialized"),
+            Self::NoTimer(_) => f.write_str("thread pool timer disabled"),
+        }
+    }
+}
+
+impl<T> std::error::Error for SpawnError<T> {}
@@ -0,0 +1,239 @@
+use crate::{
+    channel::{GroupNotify, Notifier},
+    error::SpawnError,
+    handle::Handle,
+    join::{AbortHandle, JoinError, JoinHandle},
+    task::Task,
+};
+use crossbeam_utils::sync::Parker;
+use parking_lot::Mutex;
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+    future::{poll_fn, Future},
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+/// The tasks of a group which finished, in the order they did.
+struct GroupState {
+    finished: Mutex<Finished>,
+}
+
+struct Finished {
+    /// The ids of the tasks which finished and weren't joined yet.
+    ids: VecDeque<usize>,
+    /// The notifier of the thread waiting for the next task to finish.
+    notifier: Option<Notifier>,
+}
+
+impl GroupNotify for GroupState {
+    fn notify(&self, id: usize) {
+        let notifier = {
+            let mut finished = self.finished.lock();
+            finished.ids.push_back(id);
+            finished.notifier.take()
+        };
+
+        if let Some(notifier) = notifier {
+            notifier.notify();
+        }
+    }
+}
+
+/// A group of tasks spawned into the same pool, which allows to retrieve their outputs in the
+/// order they finish.
+///
+/// Dropping the group [aborts](Self::abort_all) every task of it which didn't start running
+/// yet.
+pub struct TaskGroup<T: Send + 'static> {
+    handle: Handle,
+    state: Arc<GroupState>,
+    /// The handles of the tasks which weren't joined yet, by id.
+    tasks: HashMap<usize, JoinHandle<T>>,
+    next_id: usize,
+}
+
+impl<T: Send + 'static> TaskGroup<T> {
+    /// Creates an empty group which spawns its tasks into the currently running thread pool.
+    pub fn new() -> Self {
+        Self::with_handle(Handle::current())
+    }
+
+    /// Creates an empty group which spawns its tasks into the pool of the given
+    /// [handle](Handle).
+    pub fn with_handle(handle: Handle) -> Self {
+        Self {
+            handle,
+            state: Arc::new(GroupState {
+                finished: Mutex::new(Finished {
+                    ids: VecDeque::new(),
+                    notifier: None,
+                }),
+            }),
+            tasks: HashMap::new(),
+            next_id: 0,
+        }
+    }
+
+    /// The number of tasks of the group which weren't joined yet.
+    pub fn len(&self) -> usize {
+        self.tasks.len()
+    }
+
+    /// Whether the group has no tasks left to join.
+    pub fn is_empty(&self) -> bool {
+        self.tasks.is_empty()
+    }
+
+    /// Adds a spawned task to the group.
+    fn insert(&mut self, handle: JoinHandle<T>) -> AbortHandle {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+
+        let abort = handle.abort_handle();
+        handle.register_group(Arc::clone(&self.state) as Arc<dyn GroupNotify>, id);
+        self.tasks.insert(id, handle);
+
+        abort
+    }
+
+    /// Spawns a new task into the group, returning a handle which can be used to abort it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the task can't be spawned, like [Handle::spawn](Handle::spawn).
+    #[track_caller]
+    pub fn spawn<F>(&mut self, task: F) -> AbortHandle
+    where
+        F: Task<Output = T>,
+    {
+        let handle = self.handle.spawn(task);
+        self.insert(handle)
+    }
+
+    /// Tries to spawn a new task into the group, giving back the task if it couldn't be spawned.
+    #[track_caller]
+    pub fn try_spawn<F>(&mut self, task: F) -> Result<AbortHandle, SpawnError<F>>
+    where
+        F: Task<Output = T>,
+    {
+        let handle = self.handle.try_spawn(task)?;
+        Ok(self.insert(handle))
+    }
+
+    /// Spawns a new asynchronous task into the group, returning a handle which can be used to
+    /// abort it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the task can't be spawned, like [Handle::spawn_async](Handle::spawn_async).
+    #[track_caller]
+    pub fn spawn_async<F>(&mut self, future: F) -> AbortHandle
+    where
+        F: Future<Output = T> + Send + 'static,
+    {
+        let handle = self.handle.spawn_async(future);
+        self.insert(handle)
+    }
+
+    /// Takes the output of the next finished task, if any.
+    fn take_next(&mut self, finished: &mut Finished) -> Option<Result<T, JoinError>> {
+        while let Some(id) = finished.ids.pop_front() {
+            if let Some(handle) = self.tasks.remove(&id) {
+                match handle.try_wait() {
+                    Ok(value) => return Some(value),
+                    Err(handle) => {
+                        self.tasks.insert(id, handle);
+                    }
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Waits synchronously for the next task of the group to finish, returning its output, or
+    /// [None](None) if there are no tasks left to join.
+    pub fn join_next(&mut self) -> Option<Result<T, JoinError>> {
+        if self.tasks.is_empty() {
+            return None;
+        }
+
+        let parker = Parker::new();
+
+        loop {
+            {
+                let state = Arc::clone(&self.state);
+                let mut finished = state.finished.lock();
+
+                if let Some(value) = self.take_next(&mut finished) {
+                    return Some(value);
+                }
+
+                finished.notifier = Some(Notifier::Unparker(parker.unparker().clone()));
+            }
+
+            parker.park();
+        }
+    }
+
+    /// Polls for the next task of the group to finish, this is the asynchronous version of
+    /// [join_next](Self::join_next).
+    pub fn poll_join_next(&mut self, cx: &mut Context<'_>) -> Poll<Option<Result<T, JoinError>>> {
+        if self.tasks.is_empty() {
+            return Poll::Ready(None);
+        }
+
+        let state = Arc::clone(&self.state);
+        let mut finished = state.finished.lock();
+
+        match self.take_next(&mut finished) {
+            Some(value) => Poll::Ready(Some(value)),
+            None => {
+                finished.notifier = Some(Notifier::Waker(cx.waker().clone()));
+                Poll::Pending
+            }
+        }
+    }
+
+    /// Waits asynchronously for the next task of the group to finish, returning its output, or
+    /// [None](None) if there are no tasks left to join.
+    pub fn join_next_async(&mut self) -> impl Future<Output = Option<Result<T, JoinError>>> + '_ {
+        poll_fn(move |cx| self.poll_join_next(cx))
+    }
+
+    /// Waits synchronously for every task of the group, returning their outputs in the order
+    /// they finished.
+    pub fn join_all(&mut self) -> Vec<Result<T, JoinError>> {
+        std::iter::from_fn(|| self.join_next()).collect()
+    }
+
+    /// Aborts every task of the group which didn't start running yet, joining them will return
+    /// a [cancelled](JoinError::is_cancelled) error.
+    pub fn abort_all(&self) {
+        for handle in self.tasks.values() {
+            handle.abort();
+        }
+    }
+}
+
+impl<T: Send + 'static> Default for TaskGroup<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Send + 'static> fmt::Debug for TaskGroup<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TaskGroup")
+            .field("len", &self.tasks.len())
+            .finish()
+    }
+}
+
+impl<T: Send + 'static> Drop for TaskGroup<T> {
+    fn drop(&mut self) {
+        self.abort_all();
+    }
+}
@@ -1,108 +1,449 @@
 use crate::{
-    channel::ChannelHalf,
+    context::EnterGuard,
+    error::SpawnError,
+    group::TaskGroup,
     join::JoinHandle,
-    shared::Shared,
-    task::{SyncTask, Task, TaskType},
+    metrics::PoolMetrics,
+    scope::Scope,
+    shared::{Reservation, Shared},
+    task::{Priority, Task, TaskType},
+    task_builder::TaskBuilder,
 };
-use parking_lot::Mutex;
 use std::{
-    collections::VecDeque,
+    future::Future,
     sync::{atomic::Ordering, Arc},
-    thread::JoinHandle as StdThreadJoinHandle,
 };
-use std::time::Duration;
-use crate::task::PeriodicTask;
+use std::time::{Duration, Instant};
+use crate::periodic::PeriodicHandle;
+
+/// The result of a [graceful shutdown](Handle::shutdown_graceful).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ShutdownReport {
+    /// The number of tasks which ran to completion while shutting down, the ones which panicked
+    /// or were aborted aren't counted.
+    pub completed: usize,
+    /// The number of tasks which never ran, because the timeout elapsed before they could run or
+    /// because they were delayed past the shutdown. Periodic tasks aren't counted.
+    pub discarded: usize,
+}
 
 /// A handle used to spawn tasks into the thread pool.
 #[derive(Clone)]
 pub struct Handle {
     /// The shared data between all workers.
     pub(crate) shared: Arc<Shared>,
-    /// The handles of all worker threads.
-    handles: Arc<Mutex<VecDeque<StdThreadJoinHandle<()>>>>,
 }
 
 impl Handle {
-    pub(crate) fn new(shared: Arc<Shared>, handles: VecDeque<StdThreadJoinHandle<()>>) -> Self {
-        Self {
-            shared,
-            handles: Arc::new(Mutex::new(handles)),
-        }
+    pub(crate) fn new(shared: Arc<Shared>) -> Self {
+        Self { shared }
     }
 
-    /// Gets the handle of the currently running thread pool.
+    /// Gets the handle of the current thread pool, which is the pool of the worker when called
+    /// from one of them, or the pool [entered](Self::enter) by this thread otherwise.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is no current pool, see [try_get](Self::try_get) for a non panicking
+    /// alternative.
     pub fn current() -> Self {
         crate::context::get_handle()
     }
 
-    /// Gets the handle of the currently running thread pool if it exists.
+    /// Gets the handle of the current thread pool if there is one, see
+    /// [current](Self::current).
     pub fn try_get() -> Option<Self> {
         crate::context::try_get()
     }
 
+    /// Gets the handle of the current thread pool, unlike [current](Self::current), returns an
+    /// error instead of panicking if there is no current pool.
+    pub fn try_current() -> Result<Self, SpawnError<()>> {
+        Self::try_get().ok_or(SpawnError::NoPool(()))
+    }
+
+    /// Makes this pool the current one of this thread, so the free functions like
+    /// [spawn](crate::spawn) use it, until the returned guard is dropped, which restores the
+    /// pool that was current before.
+    pub fn enter(&self) -> EnterGuard {
+        crate::context::enter(self.clone())
+    }
+
+    /// Returns the [name](crate::ThreadPoolBuilder::pool_name) of the pool.
+    pub fn pool_name(&self) -> &str {
+        self.shared.name()
+    }
+
+    /// Returns the current number of threads of the pool.
+    pub fn thread_count(&self) -> usize {
+        self.shared.thread_count()
+    }
+
+    /// Sets the number of threads of the pool, this sets both the
+    /// [minimum](crate::ThreadPoolBuilder::min_threads) and the
+    /// [maximum](crate::ThreadPoolBuilder::max_threads) number of threads, which can't be less
+    /// than one.
+    ///
+    /// Missing threads are spawned right away, while extra threads finish the task they are
+    /// running, if any, and exit. Returns an error if a new thread couldn't be spawned.
+    pub fn set_thread_count(&self, threads: usize) -> std::io::Result<()> {
+        self.shared.set_thread_count(threads)
+    }
+
+    /// Returns a snapshot of the [metrics](PoolMetrics) of the pool.
+    pub fn metrics(&self) -> PoolMetrics {
+        self.shared.metrics()
+    }
+
+    /// Renders the metrics of the pool in the Prometheus text format, see
+    /// [prometheus](crate::prometheus) to export many pools together.
+    #[cfg(feature = "metrics")]
+    pub fn render_metrics(&self) -> String {
+        crate::prometheus::render(std::slice::from_ref(self))
+    }
+
+    /// Starts serving the metrics of the pool in the Prometheus text format on the given
+    /// address, see [MetricsServer](crate::prometheus::MetricsServer).
+    #[cfg(feature = "metrics")]
+    pub fn serve_metrics(
+        &self,
+        addr: impl std::net::ToSocketAddrs
+    ) -> std::io::Result<crate::prometheus::MetricsServer> {
+        crate::prometheus::MetricsServer::bind(addr, vec![self.clone()])
+    }
+
     /// Shuts down the thread pool, waiting for all threads to exit.
+    ///
+    /// Tasks still waiting in the queue are discarded, waiting for them will return a
+    /// [shutdown](crate::JoinError::is_shutdown) error. The timer of the pool is stopped too,
+    /// cancelling its delayed and periodic tasks.
     pub fn shutdown(self) {
-        crate::context::delete_handle();
+        self.shared.close();
+        self.shared.shutdown_timer();
         self.shared.exit.swap(true, Ordering::Relaxed);
-        self.shared.condvar.notify_all();
-        let mut lock = self.handles.lock();
+        self.shared.notify_all();
+        self.shared.join_workers();
+        self.clean();
+    }
 
-        while let Some(handle) = lock.pop_front() {
-            handle.join().expect("Failed to join thread");
+    /// Shuts down the thread pool gracefully, no more tasks are accepted and workers keep
+    /// running the queued ones until the queue is empty or the given [timeout](Duration)
+    /// elapses, once that happens the remaining tasks are discarded like in
+    /// [shutdown](Self::shutdown).
+    ///
+    /// Tasks which are already running when the timeout elapses are allowed to finish.
+    pub fn shutdown_graceful(self, timeout: Duration) -> ShutdownReport {
+        let deadline = Instant::now() + timeout;
+        let completed = self.shared.counters.completed();
+
+        self.shared.close();
+
+        // If the queue couldn't be drained in time, make the workers exit right away.
+        if !self.shared.wait_workers(deadline) {
+            self.shared.exit.store(true, Ordering::Relaxed);
+            self.shared.notify_all();
         }
 
-        self.clean();
+        self.shared.join_workers();
+        let delayed = self.shared.shutdown_timer();
+
+        ShutdownReport {
+            completed: self.shared.counters.completed() - completed,
+            discarded: self.clean() + delayed,
+        }
     }
 
-    fn clean(&self) {
-        let mut lock = self.shared.queue.lock();
-        while let Some(task) = lock.pop_front() {
-            match task {
-                TaskType::Sync(task) => drop(task),
-                TaskType::Periodic(task) => drop(task)
-            }
+    /// Discards all tasks left in the queue, returning how many of them there were.
+    fn clean(&self) -> usize {
+        let mut discarded = 0;
+
+        while let Some(task) = self.shared.pop_global() {
+            task.discard();
+            discarded += 1;
         }
+
+        discarded
+    }
+
+    /// Returns a [builder](TaskBuilder) which allows to configure a task, like its
+    /// [priority](Priority), before spawning it.
+    pub fn task_builder(&self) -> TaskBuilder<'_> {
+        TaskBuilder::new(self)
     }
 
     /// Spawns a new task into the thread pool, returning a handle which can be used to retrieve
     /// the output of the task.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the task can't be spawned, see [try_spawn](Self::try_spawn) for a non
+    /// panicking alternative.
+    #[track_caller]
     pub fn spawn<T, R>(&self, task: T) -> JoinHandle<R>
     where
         T: Task<Output = R>,
         R: Sized + Send + 'static,
     {
-        let (rx, tx) = ChannelHalf::<R>::new_pair();
-        self.shared
-            .schedule(TaskType::Sync(SyncTask::new(Some(tx), task)));
-        JoinHandle::new(rx)
+        self.task_builder().spawn(task)
+    }
+
+    /// Spawns a new task with the given [priority](Priority) into the thread pool, returning a
+    /// handle which can be used to retrieve the output of the task.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the task can't be spawned, use [task_builder](Self::task_builder) for a non
+    /// panicking alternative.
+    #[track_caller]
+    pub fn spawn_with_priority<T, R>(&self, priority: Priority, task: T) -> JoinHandle<R>
+    where
+        T: Task<Output = R>,
+        R: Sized + Send + 'static,
+    {
+        self.task_builder().priority(priority).spawn(task)
+    }
+
+    /// Tries to spawn a new task into the thread pool, returning a handle which can be used to
+    /// retrieve the output of the task, or the task itself if it couldn't be spawned.
+    #[track_caller]
+    pub fn try_spawn<T, R>(&self, task: T) -> Result<JoinHandle<R>, SpawnError<T>>
+    where
+        T: Task<Output = R>,
+        R: Sized + Send + 'static,
+    {
+        self.task_builder().try_spawn(task)
     }
 
     /// Spawns a new task into the pool, but unlike [`spawn`](Self::spawn), doesn't return a
     /// handle to retrieve the output of the task, this is useful to avoid the allocation needed
     /// to retrieve the output when it's not needed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the task can't be spawned, see [try_spawn_detached](Self::try_spawn_detached)
+    /// for a non panicking alternative.
+    #[track_caller]
     pub fn spawn_detached<T, R>(&self, task: T)
     where
         T: Task<Output = R>,
         R: Sized + Send + 'static,
     {
-        self.shared
-            .schedule(TaskType::Sync(SyncTask::new(None, task)));
+        self.task_builder().spawn_detached(task)
+    }
+
+    /// Tries to spawn a new task into the pool without returning a handle, giving back the task
+    /// if it couldn't be spawned.
+    #[track_caller]
+    pub fn try_spawn_detached<T, R>(&self, task: T) -> Result<(), SpawnError<T>>
+    where
+        T: Task<Output = R>,
+        R: Sized + Send + 'static,
+    {
+        self.task_builder().try_spawn_detached(task)
+    }
+
+    /// Spawns a new asynchronous task into the thread pool, returning a handle which can be used
+    /// to retrieve the output of the future.
+    ///
+    /// The future is polled by the workers of the pool, every time it's woken it's queued again
+    /// like any other task, so it shouldn't block the thread for long periods of time.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the task can't be spawned, see [try_spawn_async](Self::try_spawn_async) for a
+    /// non panicking alternative.
+    #[track_caller]
+    pub fn spawn_async<F>(&self, future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        self.task_builder().spawn_async(future)
+    }
+
+    /// Tries to spawn a new asynchronous task into the thread pool, returning a handle which can
+    /// be used to retrieve the output of the future, or the future itself if it couldn't be
+    /// spawned.
+    #[track_caller]
+    pub fn try_spawn_async<F>(&self, future: F) -> Result<JoinHandle<F::Output>, SpawnError<F>>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        self.task_builder().try_spawn_async(future)
+    }
+
+    /// Creates an empty [group](TaskGroup) which spawns its tasks into this pool.
+    pub fn task_group<T: Send + 'static>(&self) -> TaskGroup<T> {
+        TaskGroup::with_handle(self.clone())
+    }
+
+    /// Runs the future to completion on the current thread, returning its output.
+    ///
+    /// When called from a worker of this pool, the worker keeps running queued tasks while
+    /// the future is pending instead of just blocking, so futures waiting for tasks of the same
+    /// pool don't deadlock it.
+    pub fn block_on<F: Future>(&self, future: F) -> F::Output {
+        crate::block_on::block_on(Some(&self.shared), future)
+    }
+
+    /// Creates a [scope](Scope) and runs the given function with it, tasks spawned into the
+    /// scope can borrow data from the stack of the caller, as every one of them finishes before
+    /// this returns.
+    ///
+    /// If the function or any of the tasks panic, the panic is propagated once every task
+    /// finishes. When called from a worker of this pool, the worker keeps running queued tasks
+    /// while waiting for the ones of the scope.
+    pub fn scope<'scope, F, R>(&self, fun: F) -> R
+    where
+        F: FnOnce(&Scope<'scope>) -> R,
+    {
+        crate::scope::scope(self, fun)
+    }
+
+    /// Runs both functions, potentially in parallel, returning their outputs.
+    ///
+    /// The first function runs on the current thread, while the second one can be stolen by an
+    /// idle worker, if none took it by the time the first one finishes, it runs on the current
+    /// thread too. When called from a worker of this pool, the worker keeps running queued
+    /// tasks while waiting for the second function, so recursive divide and conquer algorithms
+    /// don't deadlock the pool.
+    ///
+    /// If any of the functions panic, the panic is propagated once both of them finish.
+    pub fn join<A, B, RA, RB>(&self, oper_a: A, oper_b: B) -> (RA, RB)
+    where
+        A: FnOnce() -> RA + Send,
+        B: FnOnce() -> RB + Send,
+        RA: Send,
+        RB: Send,
+    {
+        crate::scope::join(self, oper_a, oper_b)
+    }
+
+    /// Spawns the task created by the given function, following the
+    /// [rejection policy](crate::RejectionPolicy) of the pool if the queue is full.
+    pub(crate) fn spawn_inner<T, F>(&self, task: T, make: F) -> Result<(), SpawnError<T>>
+    where
+        F: FnOnce(T) -> TaskType,
+    {
+        let guard = match self.shared.enter() {
+            Ok(guard) => guard,
+            Err(error) => return Err(error.with(task)),
+        };
+
+        match self.shared.reserve() {
+            Reservation::Queue => {
+                self.shared.counters.spawned();
+                self.shared.push(&guard, make(task));
+            }
+            Reservation::RunInline => {
+                self.shared.counters.spawned();
+                // Don't keep the pool from closing while the task runs.
+                drop(guard);
+                let outcome = make(task).run(&self.shared, None);
+                self.shared.counters.ran(outcome);
+            }
+            Reservation::Full => return Err(SpawnError::QueueFull(task)),
+            Reservation::Closed => return Err(SpawnError::Shutdown(task)),
+        }
+
+        Ok(())
+    }
+
+    /// Spawns a new task into the thread pool once the given [delay](Duration) elapses, returning
+    /// a handle which can be used to retrieve the output of the task.
+    ///
+    /// The task is accepted right away, so the [capacity](crate::ThreadPoolBuilder::queue_capacity)
+    /// of the queue doesn't apply to it once it's due. If the pool shuts down before that, the
+    /// task is discarded.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the pool was shut down or its [timer](crate::ThreadPoolBuilder::timer) is
+    /// disabled, see [try_spawn_after](Self::try_spawn_after) for a non panicking alternative.
+    #[track_caller]
+    pub fn spawn_after<T, R>(&self, delay: Duration, task: T) -> JoinHandle<R>
+    where
+        T: Task<Output = R>,
+        R: Sized + Send + 'static,
+    {
+        self.task_builder().spawn_after(delay, task)
+    }
+
+    /// Tries to spawn a new task into the thread pool once the given [delay](Duration) elapses,
+    /// see [spawn_after](Self::spawn_after) for more information, the task is given back if it
+    /// couldn't be spawned.
+    #[track_caller]
+    pub fn try_spawn_after<T, R>(
+        &self,
+        delay: Duration,
+        task: T
+    ) -> Result<JoinHandle<R>, SpawnError<T>>
+    where
+        T: Task<Output = R>,
+        R: Sized + Send + 'static,
+    {
+        self.task_builder().try_spawn_after(delay, task)
+    }
+
+    /// Spawns a new task into the thread pool once the given [instant](Instant) is reached,
+    /// returning a handle which can be used to retrieve the output of the task, see
+    /// [spawn_after](Self::spawn_after) for more information.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the pool was shut down or its [timer](crate::ThreadPoolBuilder::timer) is
+    /// disabled, see [try_spawn_at](Self::try_spawn_at) for a non panicking alternative.
+    #[track_caller]
+    pub fn spawn_at<T, R>(&self, at: Instant, task: T) -> JoinHandle<R>
+    where
+        T: Task<Output = R>,
+        R: Sized + Send + 'static,
+    {
+        self.task_builder().spawn_at(at, task)
+    }
+
+    /// Tries to spawn a new task into the thread pool once the given [instant](Instant) is
+    /// reached, see [spawn_after](Self::spawn_after) for more information, the task is given
+    /// back if it couldn't be spawned.
+    #[track_caller]
+    pub fn try_spawn_at<T, R>(&self, at: Instant, task: T) -> Result<JoinHandle<R>, SpawnError<T>>
+    where
+        T: Task<Output = R>,
+        R: Sized + Send + 'static,
+    {
+        self.task_builder().try_spawn_at(at, task)
     }
 
     /// Creates a new periodic task that will be ran every [every](Duration) time and the number
     /// of times given, if the number of times given is [None](None) the task will run until the
-    /// thread pool gets closed.
-    pub fn periodic<F>(&self, fun: F, every: Duration, times: Option<usize>)
+    /// thread pool gets closed or the task gets cancelled using the returned
+    /// [handle](PeriodicHandle).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the pool was shut down or its [timer](crate::ThreadPoolBuilder::timer) is
+    /// disabled, see [try_periodic](Self::try_periodic) for a non panicking alternative.
+    #[track_caller]
+    pub fn periodic<F>(&self, fun: F, every: Duration, times: Option<usize>) -> PeriodicHandle
     where
         F: Fn() + Send + 'static
     {
-        if self.shared.should_exit() {
-            panic!("Cannot spawn a task, thread pool exited.");
-        }
-
-        let task = PeriodicTask::new(Arc::clone(&self.shared), fun, every, times);
+        self.task_builder().periodic(fun, every, times)
+    }
 
-        crate::context::get_timer()
-            .schedule(task);
+    /// Tries to create a new periodic task, see [periodic](Self::periodic) for more
+    /// information, the function is given back if the task couldn't be created.
+    #[track_caller]
+    pub fn try_periodic<F>(
+        &self,
+        fun: F,
+        every: Duration,
+        times: Option<usize>
+    ) -> Result<PeriodicHandle, SpawnError<F>>
+    where
+        F: Fn() + Send + 'static
+    {
+        self.task_builder().try_periodic(fun, every, times)
     }
 }
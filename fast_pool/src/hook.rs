@@ -0,0 +1,88 @@
+use std::sync::Arc;
+use std::thread::ThreadId;
+use std::time::{Duration, Instant};
+
+/// The worker a hook runs on, given to the hooks set with
+/// [on_start_with](crate::ThreadPoolBuilder::on_start_with) and the like.
+#[derive(Debug, Clone, Copy)]
+pub struct WorkerContext<'a> {
+    index: usize,
+    thread_id: ThreadId,
+    pool_name: &'a str,
+}
+
+impl<'a> WorkerContext<'a> {
+    pub(crate) fn new(index: usize, pool_name: &'a str) -> Self {
+        Self {
+            index,
+            thread_id: std::thread::current().id(),
+            pool_name,
+        }
+    }
+
+    /// The position of the worker inside the pool, indexes of retired workers are reused by
+    /// the ones spawned later, so they can be used to key per worker resources.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// The id of the thread running the worker.
+    pub fn thread_id(&self) -> ThreadId {
+        self.thread_id
+    }
+
+    /// The [name](crate::ThreadPoolBuilder::pool_name) of the pool the worker belongs to.
+    pub fn pool_name(&self) -> &'a str {
+        self.pool_name
+    }
+}
+
+/// The kind of a task, see [TaskInfo](TaskInfo).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TaskKind {
+    /// A task spawned with [spawn](crate::Handle::spawn) or the like.
+    Sync,
+    /// A run of a [periodic](crate::Handle::periodic) task.
+    Periodic,
+    /// A poll of an [asynchronous](crate::Handle::spawn_async) task.
+    Async,
+}
+
+/// The task a hook runs for, given to the hooks set with
+/// [before_with](crate::ThreadPoolBuilder::before_with) and
+/// [after_with](crate::ThreadPoolBuilder::after_with).
+#[derive(Debug, Clone)]
+pub struct TaskInfo {
+    pub(crate) id: u64,
+    pub(crate) name: Option<Arc<str>>,
+    pub(crate) kind: TaskKind,
+    pub(crate) queued_at: Instant,
+    pub(crate) elapsed: Option<Duration>,
+}
+
+impl TaskInfo {
+    /// The unique id of the task, every run of a periodic task shares the same id.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// The [name](crate::TaskBuilder::name) of the task, if it was given one.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Whether the task is synchronous, periodic or asynchronous.
+    pub fn kind(&self) -> TaskKind {
+        self.kind
+    }
+
+    /// The last time the task was pushed into the queues.
+    pub fn queued_at(&self) -> Instant {
+        self.queued_at
+    }
+
+    /// The time the task ran for, [None](None) in the hooks called before it runs.
+    pub fn elapsed(&self) -> Option<Duration> {
+        self.elapsed
+    }
+}
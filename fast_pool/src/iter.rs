@@ -0,0 +1,414 @@
+//! Parallel iterators which run on the current thread pool.
+//!
+//! Slices can be iterated with [par_iter](IntoParallelRefIterator::par_iter), while vectors
+//! and ranges can be consumed with [into_par_iter](IntoParallelIterator::into_par_iter):
+//!
+//! ```ignore
+//! use fast_pool::iter::*;
+//!
+//! let squares = (0..1000u64).into_par_iter().map(|n| n * n).collect::<Vec<_>>();
+//! let even = squares.par_iter().filter(|n| *n % 2 == 0).count();
+//! ```
+//!
+//! Items are split into chunks which run in parallel using [join](crate::join), the number of
+//! chunks adapts to the load of the pool, splitting more when chunks get stolen by idle workers.
+
+use crate::handle::Handle;
+use std::iter::Sum;
+use std::marker::PhantomData;
+use std::ops::Range;
+
+/// A source of items which can be split in two, so each half is processed in parallel.
+pub trait Producer: Sized + Send {
+    type Item: Send;
+    type IntoIter: Iterator<Item = Self::Item>;
+
+    /// The number of items of the producer.
+    fn len(&self) -> usize;
+
+    /// Splits the producer in two at the given index.
+    fn split_at(self, index: usize) -> (Self, Self);
+
+    /// Converts the producer into a sequential iterator.
+    fn into_iter(self) -> Self::IntoIter;
+
+    /// Whether the producer has no items.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// A producer over the items of a slice.
+pub struct SliceProducer<'a, T>(&'a [T]);
+
+impl<'a, T: Sync> Producer for SliceProducer<'a, T> {
+    type Item = &'a T;
+    type IntoIter = std::slice::Iter<'a, T>;
+
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    fn split_at(self, index: usize) -> (Self, Self) {
+        let (left, right) = self.0.split_at(index);
+        (Self(left), Self(right))
+    }
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+/// A producer which owns the items of a vector.
+pub struct VecProducer<T>(Vec<T>);
+
+impl<T: Send> Producer for VecProducer<T> {
+    type Item = T;
+    type IntoIter = std::vec::IntoIter<T>;
+
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    fn split_at(mut self, index: usize) -> (Self, Self) {
+        let right = self.0.split_off(index);
+        (self, Self(right))
+    }
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+/// A producer over a range of integers.
+pub struct RangeProducer<T>(Range<T>);
+
+macro_rules! range_producer {
+    ($($ty:ty),*) => {
+        $(
+            impl Producer for RangeProducer<$ty> {
+                type Item = $ty;
+                type IntoIter = Range<$ty>;
+
+                fn len(&self) -> usize {
+                    if self.0.start < self.0.end {
+                        (self.0.end as i128 - self.0.start as i128) as usize
+                    } else {
+                        0
+                    }
+                }
+
+                fn split_at(self, index: usize) -> (Self, Self) {
+                    let middle = (self.0.start as i128 + index as i128) as $ty;
+                    (Self(self.0.start..middle), Self(middle..self.0.end))
+                }
+
+                fn into_iter(self) -> Self::IntoIter {
+                    self.0
+                }
+            }
+
+            impl IntoParallelIterator for Range<$ty> {
+                type Producer = RangeProducer<$ty>;
+
+                fn into_par_iter(self) -> IntoParIter<Self::Producer> {
+                    ParIter::new(RangeProducer(self))
+                }
+            }
+        )*
+    };
+}
+
+range_producer!(u8, u16, u32, u64, usize, i8, i16, i32, i64, isize);
+
+/// Conversion into a [parallel iterator](ParIter) which consumes the value.
+pub trait IntoParallelIterator {
+    type Producer: Producer;
+
+    fn into_par_iter(self) -> IntoParIter<Self::Producer>;
+}
+
+impl<T: Send> IntoParallelIterator for Vec<T> {
+    type Producer = VecProducer<T>;
+
+    fn into_par_iter(self) -> IntoParIter<Self::Producer> {
+        ParIter::new(VecProducer(self))
+    }
+}
+
+/// Conversion into a [parallel iterator](ParIter) over references to the items of the value.
+pub trait IntoParallelRefIterator<'a> {
+    type Producer: Producer;
+
+    fn par_iter(&'a self) -> IntoParIter<Self::Producer>;
+}
+
+impl<'a, T: Sync + 'a> IntoParallelRefIterator<'a> for [T] {
+    type Producer = SliceProducer<'a, T>;
+
+    fn par_iter(&'a self) -> IntoParIter<Self::Producer> {
+        ParIter::new(SliceProducer(self))
+    }
+}
+
+impl<'a, T: Sync + 'a> IntoParallelRefIterator<'a> for Vec<T> {
+    type Producer = SliceProducer<'a, T>;
+
+    fn par_iter(&'a self) -> IntoParIter<Self::Producer> {
+        ParIter::new(SliceProducer(self))
+    }
+}
+
+/// The adapters of a parallel iterator, which take an item of the producer and pass the
+/// resulting items, if any, to the given sink.
+pub trait Pipeline<I, T>: Fn(I, &mut dyn FnMut(T)) + Sync {}
+
+impl<I, T, F: Fn(I, &mut dyn FnMut(T)) + Sync> Pipeline<I, T> for F {}
+
+/// The pipeline of an iterator without adapters, which passes every item as is.
+pub type Identity<T> = fn(T, &mut dyn FnMut(T));
+
+/// A parallel iterator over the items of the producer, without adapters.
+pub type IntoParIter<P> = ParIter<P, <P as Producer>::Item, Identity<<P as Producer>::Item>>;
+
+fn identity<T>(item: T, sink: &mut dyn FnMut(T)) {
+    sink(item)
+}
+
+/// A parallel iterator, this is created by [into_par_iter](IntoParallelIterator::into_par_iter)
+/// and [par_iter](IntoParallelRefIterator::par_iter).
+///
+/// Every item of the producer goes through the [pipeline](Pipeline) of adapters, like
+/// [map](Self::map) or [filter](Self::filter).
+pub struct ParIter<P, T, F> {
+    producer: P,
+    pipeline: F,
+    min_len: usize,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<P: Producer> ParIter<P, P::Item, Identity<P::Item>> {
+    fn new(producer: P) -> Self {
+        Self {
+            producer,
+            pipeline: identity,
+            min_len: 1,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<P, T, F> ParIter<P, T, F>
+where
+    P: Producer,
+    T: Send,
+    F: Pipeline<P::Item, T>,
+{
+    /// Replaces the pipeline with the one built from the current pipeline.
+    fn with_pipeline<U, G>(self, make: impl FnOnce(F) -> G) -> ParIter<P, U, G> {
+        ParIter {
+            producer: self.producer,
+            pipeline: make(self.pipeline),
+            min_len: self.min_len,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Sets the minimum number of items processed sequentially, chunks aren't split further
+    /// once they reach this length, by default 1.
+    pub fn with_min_len(mut self, min_len: usize) -> Self {
+        self.min_len = min_len.max(1);
+        self
+    }
+
+    /// Applies the function to every item.
+    pub fn map<U, M>(self, map: M) -> ParIter<P, U, impl Pipeline<P::Item, U>>
+    where
+        U: Send,
+        M: Fn(T) -> U + Sync,
+    {
+        self.with_pipeline(|pipeline| move |item, sink: &mut dyn FnMut(U)| {
+            pipeline(item, &mut |item| sink(map(item)))
+        })
+    }
+
+    /// Keeps only the items for which the predicate returns true.
+    pub fn filter<Pr>(self, predicate: Pr) -> ParIter<P, T, impl Pipeline<P::Item, T>>
+    where
+        Pr: Fn(&T) -> bool + Sync,
+    {
+        self.with_pipeline(|pipeline| move |item, sink: &mut dyn FnMut(T)| {
+            pipeline(item, &mut |item| if predicate(&item) {
+                sink(item)
+            })
+        })
+    }
+
+    /// Calls the function with every item.
+    pub fn for_each<C>(self, fun: C)
+    where
+        C: Fn(T) + Sync,
+    {
+        self.drive(|| (), |(), item| fun(item), |(), ()| ())
+    }
+
+    /// Reduces the items into a single one using the given operation, which must be
+    /// associative, as items are reduced in chunks. The identity is used as the starting value
+    /// of every chunk, so it must not change the result when reduced with any item.
+    pub fn reduce<I, O>(self, identity: I, op: O) -> T
+    where
+        I: Fn() -> T + Sync,
+        O: Fn(T, T) -> T + Sync,
+    {
+        self.drive(identity, &op, &op)
+    }
+
+    /// Sums the items.
+    pub fn sum<S>(self) -> S
+    where
+        S: Sum<T> + Sum<S> + Send,
+    {
+        self.drive(
+            || std::iter::empty::<T>().sum(),
+            |sum, item| [sum, std::iter::once(item).sum()].into_iter().sum(),
+            |left, right| [left, right].into_iter().sum()
+        )
+    }
+
+    /// Counts the items.
+    pub fn count(self) -> usize {
+        self.drive(|| 0, |count, _| count + 1, |left, right| left + right)
+    }
+
+    /// Collects the items into a collection, keeping their order.
+    pub fn collect<C>(self) -> C
+    where
+        C: FromIterator<T>,
+    {
+        let items = self.drive(
+            Vec::new,
+            |mut items, item| {
+                items.push(item);
+                items
+            },
+            |mut left, mut right| {
+                left.append(&mut right);
+                left
+            }
+        );
+
+        items.into_iter().collect()
+    }
+
+    /// Folds every chunk of items and reduces the results of the chunks, running on the
+    /// current thread pool.
+    fn drive<A, I, Fo, R>(self, identity: I, fold: Fo, reduce: R) -> A
+    where
+        A: Send,
+        I: Fn() -> A + Sync,
+        Fo: Fn(A, T) -> A + Sync,
+        R: Fn(A, A) -> A + Sync,
+    {
+        let handle = Handle::current();
+        let consumer = Consumer {
+            handle: &handle,
+            pipeline: &self.pipeline,
+            min_len: self.min_len,
+            identity: &identity,
+            fold: &fold,
+            reduce: &reduce,
+            _marker: PhantomData,
+        };
+        let splitter = Splitter::new(handle.shared.thread_count());
+
+        consumer.consume(self.producer, splitter, false)
+    }
+}
+
+/// Decides whether to keep splitting the items. It starts splitting enough to give a chunk to
+/// every worker, and splits further whenever a chunk gets stolen, which means there are idle
+/// workers.
+#[derive(Clone, Copy)]
+struct Splitter {
+    splits: usize,
+    threads: usize,
+}
+
+impl Splitter {
+    fn new(threads: usize) -> Self {
+        Self {
+            splits: threads,
+            threads,
+        }
+    }
+
+    fn try_split(&mut self, stolen: bool) -> bool {
+        if stolen {
+            self.splits = self.threads.max(self.splits / 2);
+            true
+        } else if self.splits > 0 {
+            self.splits /= 2;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// The functions used to consume the items of a parallel iterator.
+struct Consumer<'a, T, F, I, Fo, R> {
+    handle: &'a Handle,
+    pipeline: &'a F,
+    min_len: usize,
+    identity: &'a I,
+    fold: &'a Fo,
+    reduce: &'a R,
+    _marker: PhantomData<fn(T)>,
+}
+
+impl<T, F, I, Fo, R> Clone for Consumer<'_, T, F, I, Fo, R> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T, F, I, Fo, R> Copy for Consumer<'_, T, F, I, Fo, R> {}
+
+impl<T, F, I, Fo, R> Consumer<'_, T, F, I, Fo, R> {
+    /// Consumes the items of the producer, splitting them in two halves which run in parallel
+    /// while the splitter allows it, the given flag tells whether this chunk was stolen.
+    fn consume<P, A>(self, producer: P, mut splitter: Splitter, stolen: bool) -> A
+    where
+        P: Producer,
+        T: Send,
+        A: Send,
+        F: Pipeline<P::Item, T>,
+        I: Fn() -> A + Sync,
+        Fo: Fn(A, T) -> A + Sync,
+        R: Fn(A, A) -> A + Sync,
+    {
+        let middle = producer.len() / 2;
+
+        if middle < self.min_len || !splitter.try_split(stolen) {
+            let mut value = Some((self.identity)());
+
+            for item in producer.into_iter() {
+                (self.pipeline)(item, &mut |item| {
+                    value = value.take().map(|value| (self.fold)(value, item));
+                });
+            }
+
+            return value.expect("Parallel iterator chunk without value");
+        }
+
+        let (left, right) = producer.split_at(middle);
+        let origin = std::thread::current().id();
+
+        let (left, right) = self.handle.join(
+            || self.consume(left, splitter, false),
+            || self.consume(right, splitter, std::thread::current().id() != origin)
+        );
+
+        (self.reduce)(left, right)
+    }
+}
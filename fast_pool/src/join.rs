@@ -1,10 +1,143 @@
-use crate::channel::ChannelHalf;
+use crate::channel::{Cancel, ChannelHalf, GroupNotify};
 use std::{
+    any::Any,
+    fmt,
     future::Future,
     pin::Pin,
+    sync::Arc,
     task::{Context, Poll},
+    time::{Duration, Instant},
 };
 
+enum Repr {
+    Panic(Box<dyn Any + Send + 'static>),
+    Cancelled,
+    Shutdown,
+    Rejected,
+    Abandoned,
+}
+
+/// The error returned when waiting for a task which didn't complete successfully, either
+/// because it panicked, because it was aborted before it started running, because the pool
+/// shut down before the task could run, because the task was rejected from a full queue or
+/// because its future was abandoned.
+pub struct JoinError {
+    repr: Repr,
+}
+
+impl JoinError {
+    pub(crate) fn panic(payload: Box<dyn Any + Send + 'static>) -> Self {
+        Self { repr: Repr::Panic(payload) }
+    }
+
+    pub(crate) fn cancelled() -> Self {
+        Self { repr: Repr::Cancelled }
+    }
+
+    pub(crate) fn shutdown() -> Self {
+        Self { repr: Repr::Shutdown }
+    }
+
+    pub(crate) fn rejected() -> Self {
+        Self { repr: Repr::Rejected }
+    }
+
+    pub(crate) fn abandoned() -> Self {
+        Self { repr: Repr::Abandoned }
+    }
+
+    /// Whether the task panicked.
+    pub fn is_panic(&self) -> bool {
+        matches!(self.repr, Repr::Panic(_))
+    }
+
+    /// Whether the task was [aborted](JoinHandle::abort) before it started running.
+    pub fn is_cancelled(&self) -> bool {
+        matches!(self.repr, Repr::Cancelled)
+    }
+
+    /// Whether the task was discarded because the pool shut down before it could run.
+    pub fn is_shutdown(&self) -> bool {
+        matches!(self.repr, Repr::Shutdown)
+    }
+
+    /// Whether the task was dropped because the queue of the pool was full, see
+    /// [RejectionPolicy](crate::RejectionPolicy).
+    pub fn is_rejected(&self) -> bool {
+        matches!(self.repr, Repr::Rejected)
+    }
+
+    /// Whether the asynchronous task was dropped while the pool was running because nothing
+    /// could wake its future anymore, like a future which never keeps its waker.
+    pub fn is_abandoned(&self) -> bool {
+        matches!(self.repr, Repr::Abandoned)
+    }
+
+    /// Returns the message the task panicked with, if the task panicked and the payload of the
+    /// panic is a string, which is the case when using [panic](std::panic) with a message.
+    pub fn panic_message(&self) -> Option<&str> {
+        match &self.repr {
+            Repr::Panic(payload) => payload
+                .downcast_ref::<&'static str>()
+                .copied()
+                .or_else(|| payload.downcast_ref::<String>().map(String::as_str)),
+            _ => None,
+        }
+    }
+
+    /// Consumes the error, returning the payload the task panicked with, this can be used
+    /// along with [resume_unwind](std::panic::resume_unwind) to propagate the panic.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the error doesn't come from a panic, use [try_into_panic](Self::try_into_panic)
+    /// to avoid this.
+    pub fn into_panic(self) -> Box<dyn Any + Send + 'static> {
+        self.try_into_panic()
+            .expect("`JoinError` reason is not a panic.")
+    }
+
+    /// Consumes the error, returning the payload the task panicked with, or the error itself
+    /// if it doesn't come from a panic.
+    pub fn try_into_panic(self) -> Result<Box<dyn Any + Send + 'static>, Self> {
+        match self.repr {
+            Repr::Panic(payload) => Ok(payload),
+            _ => Err(self),
+        }
+    }
+}
+
+impl fmt::Display for JoinError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.repr {
+            Repr::Panic(_) => match self.panic_message() {
+                Some(message) => write!(f, "task panicked with message {:?}", message),
+                None => f.write_str("task panicked"),
+            },
+            Repr::Cancelled => f.write_str("task was cancelled"),
+            Repr::Shutdown => f.write_str("thread pool shut down before the task could run"),
+            Repr::Rejected => f.write_str("task was rejected because the queue was full"),
+            Repr::Abandoned => f.write_str("task was abandoned because it can't be woken"),
+        }
+    }
+}
+
+impl fmt::Debug for JoinError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.repr {
+            Repr::Panic(_) => f.debug_tuple("JoinError::Panic")
+                .field(&self.panic_message())
+                .finish(),
+            Repr::Cancelled => f.write_str("JoinError::Cancelled"),
+            Repr::Shutdown => f.write_str("JoinError::Shutdown"),
+            Repr::Rejected => f.write_str("JoinError::Rejected"),
+            Repr::Abandoned => f.write_str("JoinError::Abandoned"),
+        }
+    }
+}
+
+impl std::error::Error for JoinError {}
+
 /// A handle used to wait for output values of tasks.
 ///
 /// This is returned by [spawn](crate::handle::Handle::spawn) and
@@ -17,15 +150,87 @@ impl<T: Send + Sized + 'static> JoinHandle<T> {
     }
 
     /// Waits synchronously for the output of this task.
-    pub fn wait(self) -> Result<T, Box<dyn std::any::Any + Send + 'static>> {
+    pub fn wait(self) -> Result<T, JoinError> {
         self.0.wait()
     }
+
+    /// Retrieves the output of the task without waiting, if the task didn't finish yet the
+    /// handle is given back so it can be waited again.
+    pub fn try_wait(self) -> Result<Result<T, JoinError>, Self> {
+        match self.0.try_get() {
+            Some(value) => Ok(value),
+            None => Err(self),
+        }
+    }
+
+    /// Waits synchronously for the output of this task for at most the given
+    /// [duration](Duration), if the task didn't finish by then the handle is given back so it
+    /// can be waited again.
+    pub fn wait_timeout(self, timeout: Duration) -> Result<Result<T, JoinError>, Self> {
+        self.wait_deadline(Instant::now() + timeout)
+    }
+
+    /// Waits synchronously for the output of this task until the given [instant](Instant), if
+    /// the task didn't finish by then the handle is given back so it can be waited again.
+    pub fn wait_deadline(self, deadline: Instant) -> Result<Result<T, JoinError>, Self> {
+        self.0.wait_deadline(deadline).map_err(Self)
+    }
+
+    /// Whether the task finished, either by completing or by being aborted, if this returns
+    /// true, the output can be retrieved without blocking.
+    pub fn is_finished(&self) -> bool {
+        self.0.is_set()
+    }
+
+    /// Aborts the task if it didn't start running yet, the task will be discarded instead of
+    /// being ran and waiting for it will return a [cancelled](JoinError::is_cancelled) error.
+    ///
+    /// Returns whether the task was aborted, tasks which already started running can't be
+    /// aborted and will run until completion.
+    pub fn abort(&self) -> bool {
+        self.0.cancel()
+    }
+
+    /// Returns an [AbortHandle](AbortHandle) which can be used to abort the task without
+    /// owning this handle.
+    pub fn abort_handle(&self) -> AbortHandle {
+        AbortHandle(self.0.cancel_handle())
+    }
+
+    /// Registers the group to be notified with the given id once the task finishes.
+    pub(crate) fn register_group(&self, group: Arc<dyn GroupNotify>, id: usize) {
+        self.0.register_group(group, id)
+    }
+}
+
+impl<T: Send + Sized + 'static> fmt::Debug for JoinHandle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JoinHandle")
+            .field("finished", &self.is_finished())
+            .finish()
+    }
 }
 
 impl<T: Send + Sized + 'static> Future for JoinHandle<T> {
-    type Output = Result<T, Box<dyn std::any::Any + Send + 'static>>;
+    type Output = Result<T, JoinError>;
 
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         self.get_mut().0.wait_async(cx)
     }
 }
+
+/// A handle used to abort a task, this is returned by [abort_handle](JoinHandle::abort_handle).
+///
+/// Unlike [JoinHandle](JoinHandle), this can be cloned and doesn't allow to retrieve the output
+/// of the task.
+#[derive(Clone)]
+pub struct AbortHandle(Arc<dyn Cancel>);
+
+impl AbortHandle {
+    /// Aborts the task if it didn't start running yet, returning whether it was aborted.
+    ///
+    /// See [JoinHandle::abort](JoinHandle::abort) for more information.
+    pub fn abort(&self) -> bool {
+        self.0.cancel()
+    }
+}
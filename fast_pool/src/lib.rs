@@ -1,21 +1,43 @@
 #![feature(drain_filter)]
 
+mod block_on;
 mod builder;
 mod channel;
 mod context;
+mod error;
+mod group;
 mod handle;
+mod hook;
+pub mod iter;
 mod join;
+mod metrics;
+mod panic;
+mod periodic;
+#[cfg(feature = "metrics")]
+pub mod prometheus;
+mod scope;
 mod shared;
 mod task;
+mod task_builder;
 mod threadpool;
 mod timer;
 mod worker;
 
-use std::time::Duration;
-pub use builder::ThreadPoolBuilder;
-pub use handle::Handle;
-pub use join::JoinHandle;
-pub use task::Task;
+use std::future::Future;
+use std::time::{Duration, Instant};
+pub use builder::{PanicPolicy, RejectionPolicy, ThreadPoolBuilder};
+pub use context::EnterGuard;
+pub use error::SpawnError;
+pub use group::TaskGroup;
+pub use handle::{Handle, ShutdownReport};
+pub use hook::{TaskInfo, TaskKind, WorkerContext};
+pub use join::{AbortHandle, JoinError, JoinHandle};
+pub use metrics::{PoolMetrics, WorkerMetrics};
+pub use panic::TaskPanic;
+pub use periodic::PeriodicHandle;
+pub use scope::Scope;
+pub use task::{Priority, Task};
+pub use task_builder::TaskBuilder;
 pub use threadpool::ThreadPool;
 
 #[cfg(feature = "macros")]
@@ -23,6 +45,7 @@ pub use fast_pool_macros::init;
 
 /// Spawns a new task into the thread pool, returning a handle which can be used to retrieve
 /// the output of the task.
+#[track_caller]
 pub fn spawn<T, R>(task: T) -> JoinHandle<R>
 where
     T: Task<Output = R>,
@@ -31,9 +54,35 @@ where
     Handle::current().spawn(task)
 }
 
+/// Spawns a new task with the given [priority](Priority) into the thread pool, returning a
+/// handle which can be used to retrieve the output of the task.
+#[track_caller]
+pub fn spawn_with_priority<T, R>(priority: Priority, task: T) -> JoinHandle<R>
+where
+    T: Task<Output = R>,
+    R: Sized + Send + 'static,
+{
+    Handle::current().spawn_with_priority(priority, task)
+}
+
+/// Tries to spawn a new task into the thread pool, returning a handle which can be used to
+/// retrieve the output of the task, or the task itself if it couldn't be spawned.
+#[track_caller]
+pub fn try_spawn<T, R>(task: T) -> Result<JoinHandle<R>, SpawnError<T>>
+where
+    T: Task<Output = R>,
+    R: Sized + Send + 'static,
+{
+    match Handle::try_get() {
+        Some(handle) => handle.try_spawn(task),
+        None => Err(SpawnError::NoPool(task)),
+    }
+}
+
 /// Spawns a new task into the pool, but unlike [`spawn`](self::spawn), doesn't return a
 /// handle to retrieve the output of the task, this is useful to avoid the allocation needed
 /// to create the channel when the output is not needed.
+#[track_caller]
 pub fn spawn_detached<T, R>(task: T)
 where
     T: Task<Output = R>,
@@ -42,19 +91,133 @@ where
     Handle::current().spawn_detached(task)
 }
 
+/// Tries to spawn a new task into the pool without returning a handle, giving back the task if
+/// it couldn't be spawned.
+#[track_caller]
+pub fn try_spawn_detached<T, R>(task: T) -> Result<(), SpawnError<T>>
+where
+    T: Task<Output = R>,
+    R: Sized + Send + 'static,
+{
+    match Handle::try_get() {
+        Some(handle) => handle.try_spawn_detached(task),
+        None => Err(SpawnError::NoPool(task)),
+    }
+}
+
+/// Spawns a new asynchronous task into the thread pool, returning a handle which can be used
+/// to retrieve the output of the future.
+#[track_caller]
+pub fn spawn_async<F>(future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    Handle::current().spawn_async(future)
+}
+
+/// Tries to spawn a new asynchronous task into the thread pool, returning a handle which can be
+/// used to retrieve the output of the future, or the future itself if it couldn't be spawned.
+#[track_caller]
+pub fn try_spawn_async<F>(future: F) -> Result<JoinHandle<F::Output>, SpawnError<F>>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    match Handle::try_get() {
+        Some(handle) => handle.try_spawn_async(future),
+        None => Err(SpawnError::NoPool(future)),
+    }
+}
+
+/// Runs the future to completion on the current thread, returning its output.
+///
+/// If there is a current thread pool and this is called from one of its workers, the worker
+/// keeps running queued tasks while the future is pending, see
+/// [Handle::block_on](Handle::block_on).
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    let handle = Handle::try_get();
+    block_on::block_on(handle.as_ref().map(|handle| &*handle.shared), future)
+}
+
+/// Creates a [scope](Scope) in the current thread pool and runs the given function with it,
+/// tasks spawned into the scope can borrow data from the stack of the caller, see
+/// [Handle::scope](Handle::scope).
+pub fn scope<'scope, F, R>(fun: F) -> R
+where
+    F: FnOnce(&Scope<'scope>) -> R,
+{
+    Handle::current().scope(fun)
+}
+
+/// Runs both functions in the current thread pool, potentially in parallel, returning their
+/// outputs, see [Handle::join](Handle::join).
+pub fn join<A, B, RA, RB>(oper_a: A, oper_b: B) -> (RA, RB)
+where
+    A: FnOnce() -> RA + Send,
+    B: FnOnce() -> RB + Send,
+    RA: Send,
+    RB: Send,
+{
+    Handle::current().join(oper_a, oper_b)
+}
+
+/// Spawns a new task into the thread pool once the given [delay](Duration) elapses, returning
+/// a handle which can be used to retrieve the output of the task.
+#[track_caller]
+pub fn spawn_after<T, R>(delay: Duration, task: T) -> JoinHandle<R>
+where
+    T: Task<Output = R>,
+    R: Sized + Send + 'static,
+{
+    Handle::current().spawn_after(delay, task)
+}
+
+/// Spawns a new task into the thread pool once the given [instant](Instant) is reached,
+/// returning a handle which can be used to retrieve the output of the task.
+#[track_caller]
+pub fn spawn_at<T, R>(at: Instant, task: T) -> JoinHandle<R>
+where
+    T: Task<Output = R>,
+    R: Sized + Send + 'static,
+{
+    Handle::current().spawn_at(at, task)
+}
+
 /// Creates a new periodic task that will be ran every [every](Duration) time and the number
 /// of times given, if the number of times given is [None](None) the task will run until the
-/// thread pool gets closed.
-pub fn periodic<F>(fun: F, every: Duration, times: Option<usize>)
+/// thread pool gets closed or the task gets cancelled using the returned
+/// [handle](PeriodicHandle).
+#[track_caller]
+pub fn periodic<F>(fun: F, every: Duration, times: Option<usize>) -> PeriodicHandle
 where
     F: Fn() + Send + 'static
 {
     Handle::current().periodic(fun, every, times)
 }
 
+/// Tries to create a new periodic task, see [periodic](self::periodic) for more information,
+/// the function is given back if the task couldn't be created.
+#[track_caller]
+pub fn try_periodic<F>(
+    fun: F,
+    every: Duration,
+    times: Option<usize>
+) -> Result<PeriodicHandle, SpawnError<F>>
+where
+    F: Fn() + Send + 'static
+{
+    match Handle::try_get() {
+        Some(handle) => handle.try_periodic(fun, every, times),
+        None => Err(SpawnError::NoPool(fun)),
+    }
+}
+
+/// Stops the timer of the current pool, if any, dropping its delayed and periodic tasks.
+#[deprecated(note = "every pool stops its own timer when it shuts down")]
 pub fn shutdown_timer() {
-    if let Some(handle) = crate::context::get_timer_optional() {
-        handle.shutdown();
+    if let Some(handle) = Handle::try_get() {
+        handle.shared.shutdown_timer();
     }
 }
 
@@ -0,0 +1,214 @@
+use crate::task::Outcome;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::time::Duration;
+
+/// The counters of a pool, updated as tasks are spawned and ran.
+#[derive(Default)]
+pub struct Counters {
+    /// The number of tasks accepted by the pool.
+    spawned: AtomicUsize,
+    /// The number of tasks which ran to completion.
+    completed: AtomicUsize,
+    /// The number of tasks which panicked.
+    panicked: AtomicUsize,
+    /// The number of tasks which were aborted before they started running.
+    cancelled: AtomicUsize,
+    /// The number of periodic tasks which will run again.
+    periodic: AtomicUsize,
+    /// The time tasks spend waiting in the queues.
+    #[cfg(feature = "metrics")]
+    pub queue_wait: Histogram,
+    /// The time tasks spend running.
+    #[cfg(feature = "metrics")]
+    pub run_time: Histogram,
+}
+
+impl Counters {
+    pub fn spawned(&self) {
+        self.spawned.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Registers the outcome of a task ran by the pool.
+    pub fn ran(&self, outcome: Outcome) {
+        let counter = match outcome {
+            Outcome::Completed => &self.completed,
+            Outcome::Panicked => &self.panicked,
+            Outcome::Cancelled => &self.cancelled,
+            Outcome::Pending => return,
+        };
+
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// The number of tasks which ran to completion.
+    pub fn completed(&self) -> usize {
+        self.completed.load(Ordering::Relaxed)
+    }
+
+    /// Registers the time a task ran by a worker waited in the queues and the time it ran for,
+    /// this is only recorded with the `metrics` feature.
+    #[allow(unused_variables)]
+    pub fn timed(&self, wait: Duration, run: Duration) {
+        #[cfg(feature = "metrics")]
+        {
+            self.queue_wait.record(wait);
+            self.run_time.record(run);
+        }
+    }
+
+    pub fn periodic_registered(&self) {
+        self.periodic.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn periodic_finished(&self) {
+        self.periodic.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// The statistics of a single worker, shared between the worker and the pool.
+#[derive(Default)]
+pub struct WorkerStats {
+    /// Whether the worker is running a task.
+    busy: AtomicBool,
+    /// The number of tasks ran by the worker.
+    tasks: AtomicUsize,
+    /// The time spent running tasks, in nanoseconds.
+    busy_time: AtomicU64,
+}
+
+impl WorkerStats {
+    pub fn set_busy(&self, busy: bool) {
+        self.busy.store(busy, Ordering::Relaxed);
+    }
+
+    /// Registers a task ran by the worker, the time is only given for the tasks which aren't
+    /// ran while waiting inside another task, as that time is already accounted.
+    pub fn ran(&self, time: Option<Duration>) {
+        self.tasks.fetch_add(1, Ordering::Relaxed);
+
+        if let Some(time) = time {
+            let nanos = u64::try_from(time.as_nanos()).unwrap_or(u64::MAX);
+            self.busy_time.fetch_add(nanos, Ordering::Relaxed);
+        }
+    }
+
+    fn snapshot(&self, index: usize) -> WorkerMetrics {
+        WorkerMetrics {
+            index,
+            busy: self.busy.load(Ordering::Relaxed),
+            tasks: self.tasks.load(Ordering::Relaxed),
+            busy_time: Duration::from_nanos(self.busy_time.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+/// A snapshot of the metrics of a pool, returned by [metrics](crate::Handle::metrics).
+///
+/// Counters are read one by one while the pool keeps running, so they may be slightly out of
+/// sync with each other.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PoolMetrics {
+    /// The number of tasks accepted by the pool, every run of a periodic task counts as a new
+    /// task.
+    pub spawned: usize,
+    /// The number of tasks which ran to completion.
+    pub completed: usize,
+    /// The number of tasks which panicked.
+    pub panicked: usize,
+    /// The number of tasks which were [aborted](crate::JoinHandle::abort) before they started
+    /// running, or periodic runs whose task was cancelled while they were queued.
+    pub cancelled: usize,
+    /// The number of tasks waiting in the queues.
+    pub queue_depth: usize,
+    /// The number of workers running a task.
+    pub busy_workers: usize,
+    /// The number of workers waiting for tasks.
+    pub idle_workers: usize,
+    /// The number of periodic tasks which will run again.
+    pub periodic_tasks: usize,
+    /// The metrics of every worker of the pool, ordered by index.
+    pub workers: Vec<WorkerMetrics>,
+}
+
+/// A snapshot of the metrics of a single worker, see [PoolMetrics](PoolMetrics).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorkerMetrics {
+    /// The position of the worker inside the pool, indexes of retired workers are reused by
+    /// the ones spawned later.
+    pub index: usize,
+    /// Whether the worker is running a task.
+    pub busy: bool,
+    /// The number of tasks ran by the worker, every poll of an asynchronous task counts as a
+    /// run.
+    pub tasks: usize,
+    /// The time the worker spent running tasks.
+    pub busy_time: Duration,
+}
+
+/// Builds a snapshot from the counters of the pool and the statistics of its workers, by index.
+pub fn snapshot<'a>(
+    counters: &Counters,
+    queue_depth: usize,
+    workers: impl Iterator<Item = (usize, &'a WorkerStats)>
+) -> PoolMetrics {
+    let workers = workers
+        .map(|(index, stats)| stats.snapshot(index))
+        .collect::<Vec<_>>();
+    let busy_workers = workers.iter().filter(|worker| worker.busy).count();
+
+    PoolMetrics {
+        spawned: counters.spawned.load(Ordering::Relaxed),
+        completed: counters.completed.load(Ordering::Relaxed),
+        panicked: counters.panicked.load(Ordering::Relaxed),
+        cancelled: counters.cancelled.load(Ordering::Relaxed),
+        queue_depth,
+        busy_workers,
+        idle_workers: workers.len() - busy_workers,
+        periodic_tasks: counters.periodic.load(Ordering::Relaxed),
+        workers,
+    }
+}
+
+/// The upper bounds of the buckets of the latency histograms, in seconds.
+#[cfg(feature = "metrics")]
+pub const BUCKETS: [f64; 12] = [
+    0.0001, 0.00025, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 1.0, 10.0,
+];
+
+/// A histogram of durations with the fixed [buckets](BUCKETS), plus an implicit one for the
+/// durations above all of them.
+#[cfg(feature = "metrics")]
+#[derive(Default)]
+pub struct Histogram {
+    /// The number of durations which fell into every bucket, not cumulative.
+    buckets: [AtomicU64; BUCKETS.len() + 1],
+    /// The sum of every recorded duration, in nanoseconds.
+    sum: AtomicU64,
+}
+
+#[cfg(feature = "metrics")]
+impl Histogram {
+    fn record(&self, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        let bucket = BUCKETS
+            .iter()
+            .position(|&bound| seconds <= bound)
+            .unwrap_or(BUCKETS.len());
+
+        self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        let nanos = u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX);
+        self.sum.fetch_add(nanos, Ordering::Relaxed);
+    }
+
+    /// Returns the cumulative counts of every bucket, the last one being the total count, and
+    /// the sum of the recorded durations.
+    pub fn snapshot(&self) -> ([u64; BUCKETS.len() + 1], Duration) {
+        let mut total = 0;
+        let counts = std::array::from_fn(|index| {
+            total += self.buckets[index].load(Ordering::Relaxed);
+            total
+        });
+
+        (counts, Duration::from_nanos(self.sum.load(Ordering::Relaxed)))
+    }
+}
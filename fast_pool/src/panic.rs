@@ -0,0 +1,66 @@
+use crate::task::TaskMeta;
+use std::any::Any;
+use std::fmt;
+
+/// A panic of a task, given to the [panic handler](crate::ThreadPoolBuilder::panic_handler) of
+/// the pool.
+pub struct TaskPanic<'a> {
+    payload: &'a (dyn Any + Send),
+    meta: &'a TaskMeta,
+    worker: Option<usize>,
+}
+
+impl<'a> TaskPanic<'a> {
+    pub(crate) fn new(
+        payload: &'a (dyn Any + Send),
+        meta: &'a TaskMeta,
+        worker: Option<usize>
+    ) -> Self {
+        Self {
+            payload,
+            meta,
+            worker,
+        }
+    }
+
+    /// The payload the task panicked with.
+    pub fn payload(&self) -> &'a (dyn Any + Send) {
+        self.payload
+    }
+
+    /// Returns the message the task panicked with, if the payload of the panic is a string,
+    /// see [JoinError::panic_message](crate::JoinError::panic_message).
+    pub fn message(&self) -> Option<&'a str> {
+        self.payload
+            .downcast_ref::<&'static str>()
+            .copied()
+            .or_else(|| self.payload.downcast_ref::<String>().map(String::as_str))
+    }
+
+    /// The unique id of the task, every run of a periodic task shares the same id.
+    pub fn task_id(&self) -> u64 {
+        self.meta.id
+    }
+
+    /// The [name](crate::TaskBuilder::name) of the task, if it was given one.
+    pub fn task_name(&self) -> Option<&'a str> {
+        self.meta.name()
+    }
+
+    /// The index of the worker which ran the task, [None](None) if it ran on the spawning
+    /// thread because of the [rejection policy](crate::RejectionPolicy).
+    pub fn worker(&self) -> Option<usize> {
+        self.worker
+    }
+}
+
+impl fmt::Debug for TaskPanic<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TaskPanic")
+            .field("message", &self.message())
+            .field("task_id", &self.task_id())
+            .field("task_name", &self.task_name())
+            .field("worker", &self.worker)
+            .finish()
+    }
+}
@@ -0,0 +1,153 @@
+use crate::timer::TimerWaker;
+use parking_lot::Mutex;
+use std::fmt;
+use std::sync::{
+    atomic::{AtomicBool, AtomicUsize, Ordering},
+    Arc,
+};
+use std::time::{Duration, Instant};
+
+/// The state of a periodic task, shared between the task and its [handle](PeriodicHandle).
+pub(crate) struct PeriodicState {
+    /// The time between runs.
+    every: Duration,
+    /// Whether the task was cancelled.
+    cancelled: AtomicBool,
+    /// Whether the task is paused.
+    paused: AtomicBool,
+    /// The number of times the task ran.
+    runs: AtomicUsize,
+    /// The next time the task should run, [None](None) if it won't run anymore.
+    next: Mutex<Option<Instant>>,
+}
+
+impl PeriodicState {
+    pub fn new(every: Duration) -> Arc<Self> {
+        Arc::new(Self {
+            every,
+            cancelled: AtomicBool::new(false),
+            paused: AtomicBool::new(false),
+            runs: AtomicUsize::new(0),
+            next: Mutex::new(Some(Instant::now() + every)),
+        })
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Acquire)
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Acquire)
+    }
+
+    /// Whether the task is due to be ran.
+    pub fn can_run(&self) -> bool {
+        matches!(self.deadline(), Some(next) if Instant::now() >= next)
+    }
+
+    /// The next time the task should run, [None](None) if it's paused or won't run anymore.
+    pub fn deadline(&self) -> Option<Instant> {
+        if self.is_paused() {
+            None
+        } else {
+            *self.next.lock()
+        }
+    }
+
+    /// Registers a run, setting the time for the next one if there are runs left and the task
+    /// wasn't cancelled while running.
+    pub fn ran(&self, again: bool) {
+        self.runs.fetch_add(1, Ordering::AcqRel);
+        let mut next = self.next.lock();
+        *next = (again && !self.is_cancelled()).then(|| Instant::now() + self.every);
+    }
+
+    /// Skips a run without registering it, setting the time for the next one.
+    pub fn skip(&self) {
+        let mut next = self.next.lock();
+
+        if next.is_some() {
+            *next = Some(Instant::now() + self.every);
+        }
+    }
+
+    /// Marks the task as finished, so it won't run anymore.
+    pub fn finish(&self) {
+        *self.next.lock() = None;
+    }
+}
+
+/// A handle used to manage a periodic task.
+///
+/// This is returned by [periodic](crate::Handle::periodic), dropping it doesn't cancel the task.
+#[derive(Clone)]
+pub struct PeriodicHandle {
+    state: Arc<PeriodicState>,
+    /// The timer holding the task, woken when the task is resumed.
+    timer: Option<TimerWaker>,
+}
+
+impl PeriodicHandle {
+    pub(crate) fn new(state: Arc<PeriodicState>, timer: Option<TimerWaker>) -> Self {
+        Self { state, timer }
+    }
+
+    /// Cancels the task, it won't run anymore, but if it's already running it will be
+    /// allowed to finish.
+    pub fn cancel(&self) {
+        self.state.cancelled.store(true, Ordering::Release);
+        self.state.finish();
+    }
+
+    /// Whether the task was cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.state.is_cancelled()
+    }
+
+    /// Pauses the task, it won't run until [resumed](Self::resume).
+    pub fn pause(&self) {
+        self.state.paused.store(true, Ordering::Release);
+    }
+
+    /// Resumes a paused task, the next run will happen after the period of the task.
+    pub fn resume(&self) {
+        let mut next = self.state.next.lock();
+
+        if self.state.paused.swap(false, Ordering::AcqRel) && next.is_some() {
+            *next = Some(Instant::now() + self.state.every);
+            drop(next);
+
+            // The timer may be sleeping for longer than the period while nothing is due.
+            if let Some(timer) = &self.timer {
+                timer.wake();
+            }
+        }
+    }
+
+    /// Whether the task is paused.
+    pub fn is_paused(&self) -> bool {
+        self.state.is_paused()
+    }
+
+    /// Returns the number of times the task ran.
+    pub fn run_count(&self) -> usize {
+        self.state.runs.load(Ordering::Acquire)
+    }
+
+    /// Returns the next time the task will run, this is [None](None) if the task is paused,
+    /// was cancelled or ran all the times it was given.
+    pub fn next_run(&self) -> Option<Instant> {
+        self.state.deadline()
+    }
+}
+
+impl fmt::Debug for PeriodicHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PeriodicHandle")
+            .field("run_count", &self.run_count())
+            .field("next_run", &self.next_run())
+            .field("paused", &self.is_paused())
+            .field("cancelled", &self.is_cancelled())
+            .finish()
+    }
+}
@@ -0,0 +1,371 @@
+//! Exports the [metrics](crate::PoolMetrics) of thread pools in the
+//! [Prometheus text format](https://prometheus.io/docs/instrumenting/exposition_formats/),
+//! this requires the `metrics` feature.
+//!
+//! Every sample is labelled with the [name](crate::ThreadPoolBuilder::pool_name) of its pool,
+//! so many pools can be exported together.
+//!
+//! ```ignore
+//! let pool = ThreadPoolBuilder::new().pool_name("io").build()?;
+//! let server = MetricsServer::bind("127.0.0.1:9090", vec![pool.handle()])?;
+//! ```
+
+use crate::{
+    handle::Handle,
+    metrics::{Counters, Histogram, PoolMetrics, BUCKETS},
+};
+use parking_lot::Mutex;
+use std::fmt::{self, Write as _};
+use std::io::{self, Read, Write};
+use std::net::{
+    Ipv4Addr, Ipv6Addr, Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs,
+};
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    mpsc::{self, Receiver, SyncSender},
+    Arc,
+};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// The content type of the text format.
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+/// How long the server waits for a client to send its request or to take the response.
+const TIMEOUT: Duration = Duration::from_secs(5);
+/// The number of threads answering the connections.
+const RESPONDERS: usize = 4;
+/// The number of accepted connections which can wait for a responder, the rest are closed.
+const BACKLOG: usize = 64;
+
+/// The kind of a metric, written in its `TYPE` line.
+#[derive(Clone, Copy)]
+enum Kind {
+    Counter,
+    Gauge,
+    Histogram,
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Counter => "counter",
+            Self::Gauge => "gauge",
+            Self::Histogram => "histogram",
+        })
+    }
+}
+
+/// The data of a pool being rendered.
+struct Pool<'a> {
+    handle: &'a Handle,
+    /// The escaped name of the pool.
+    label: String,
+    metrics: PoolMetrics,
+}
+
+/// Writes the samples of a metric family, `write` is called once per pool.
+fn family<F>(out: &mut String, name: &str, help: &str, kind: Kind, pools: &[Pool], write: F)
+where
+    F: Fn(&mut String, &Pool),
+{
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+
+    for pool in pools {
+        write(out, pool);
+    }
+}
+
+/// Escapes a label value, as described by the text format.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// A metric with a single value per pool, its name, help, kind and how to get its value.
+type Scalar = (&'static str, &'static str, Kind, fn(&PoolMetrics) -> usize);
+
+/// The metrics with a single value per pool.
+const SCALARS: [Scalar; 8] = [
+    (
+        "fast_pool_tasks_spawned_total",
+        "Tasks accepted by the pool.",
+        Kind::Counter,
+        |metrics| metrics.spawned,
+    ),
+    (
+        "fast_pool_tasks_completed_total",
+        "Tasks which ran to completion.",
+        Kind::Counter,
+        |metrics| metrics.completed,
+    ),
+    (
+        "fast_pool_tasks_panicked_total",
+        "Tasks which panicked.",
+        Kind::Counter,
+        |metrics| metrics.panicked,
+    ),
+    (
+        "fast_pool_tasks_cancelled_total",
+        "Tasks aborted before they started running.",
+        Kind::Counter,
+        |metrics| metrics.cancelled,
+    ),
+    (
+        "fast_pool_queue_depth",
+        "Tasks waiting in the queues.",
+        Kind::Gauge,
+        |metrics| metrics.queue_depth,
+    ),
+    (
+        "fast_pool_busy_workers",
+        "Workers running a task.",
+        Kind::Gauge,
+        |metrics| metrics.busy_workers,
+    ),
+    (
+        "fast_pool_idle_workers",
+        "Workers waiting for tasks.",
+        Kind::Gauge,
+        |metrics| metrics.idle_workers,
+    ),
+    (
+        "fast_pool_periodic_tasks",
+        "Periodic tasks which will run again.",
+        Kind::Gauge,
+        |metrics| metrics.periodic_tasks,
+    ),
+];
+
+/// Renders the metrics of the given pools in the Prometheus text format.
+pub fn render(handles: &[Handle]) -> String {
+    let pools = handles
+        .iter()
+        .map(|handle| Pool {
+            handle,
+            label: escape(handle.pool_name()),
+            metrics: handle.metrics(),
+        })
+        .collect::<Vec<_>>();
+    let mut out = String::new();
+
+    for (name, help, kind, value) in SCALARS {
+        family(&mut out, name, help, kind, &pools, |out, pool| {
+            let _ = writeln!(out, "{}{{pool=\"{}\"}} {}", name, pool.label, value(&pool.metrics));
+        });
+    }
+
+    let name = "fast_pool_worker_tasks_total";
+    family(&mut out, name, "Tasks ran by every worker.", Kind::Counter, &pools, |out, pool| {
+        for worker in &pool.metrics.workers {
+            let _ = writeln!(
+                out,
+                "{}{{pool=\"{}\",worker=\"{}\"}} {}",
+                name, pool.label, worker.index, worker.tasks
+            );
+        }
+    });
+
+    let name = "fast_pool_worker_busy_seconds_total";
+    let help = "Time every worker spent running tasks.";
+    family(&mut out, name, help, Kind::Counter, &pools, |out, pool| {
+        for worker in &pool.metrics.workers {
+            let _ = writeln!(
+                out,
+                "{}{{pool=\"{}\",worker=\"{}\"}} {}",
+                name, pool.label, worker.index, worker.busy_time.as_secs_f64()
+            );
+        }
+    });
+
+    histogram(
+        &mut out,
+        "fast_pool_task_queue_wait_seconds",
+        "Time tasks waited in the queues before running.",
+        &pools,
+        |counters| &counters.queue_wait,
+    );
+    histogram(
+        &mut out,
+        "fast_pool_task_run_seconds",
+        "Time tasks spent running.",
+        &pools,
+        |counters| &counters.run_time,
+    );
+
+    out
+}
+
+/// Writes a histogram family, `get` returns the histogram from the counters of a pool.
+fn histogram<F>(out: &mut String, name: &str, help: &str, pools: &[Pool], get: F)
+where
+    F: Fn(&Counters) -> &Histogram,
+{
+    family(out, name, help, Kind::Histogram, pools, |out, pool| {
+        let (counts, sum) = get(&pool.handle.shared.counters).snapshot();
+        let bounds = BUCKETS.iter().map(f64::to_string).chain(Some(String::from("+Inf")));
+
+        for (bound, count) in bounds.zip(counts) {
+            let _ = writeln!(
+                out,
+                "{}_bucket{{pool=\"{}\",le=\"{}\"}} {}",
+                name, pool.label, bound, count
+            );
+        }
+
+        let count = counts[BUCKETS.len()];
+        let _ = writeln!(out, "{}_sum{{pool=\"{}\"}} {}", name, pool.label, sum.as_secs_f64());
+        let _ = writeln!(out, "{}_count{{pool=\"{}\"}} {}", name, pool.label, count);
+    });
+}
+
+/// A minimal HTTP server which serves the metrics of some pools, scrapers can use any path.
+///
+/// Connections are answered by a few threads, so a client which stalls doesn't delay the rest
+/// of the scrapes, it's disconnected after a few seconds. Connections accepted while too many
+/// others are waiting to be answered are closed right away. The server stops when dropped or
+/// [shut down](Self::shutdown).
+pub struct MetricsServer {
+    addr: SocketAddr,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl MetricsServer {
+    /// Starts serving the metrics of the given pools on the given address, the port can be 0
+    /// to let the system pick one, see [local_addr](Self::local_addr).
+    pub fn bind(addr: impl ToSocketAddrs, pools: Vec<Handle>) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let addr = listener.local_addr()?;
+        let stop = Arc::new(AtomicBool::new(false));
+
+        // If a thread can't be spawned the sender is dropped, stopping the ones already spawned.
+        let (sender, receiver) = mpsc::sync_channel(BACKLOG);
+        let receiver = Arc::new(Mutex::new(receiver));
+        let pools = Arc::new(pools);
+        for _ in 0..RESPONDERS {
+            let (receiver, pools) = (Arc::clone(&receiver), Arc::clone(&pools));
+            std::thread::Builder::new()
+                .name(String::from("fast_pool-metrics-responder"))
+                .spawn(move || answer(&receiver, &pools))?;
+        }
+
+        let thread = {
+            let stop = Arc::clone(&stop);
+            std::thread::Builder::new()
+                .name(String::from("fast_pool-metrics"))
+                .spawn(move || serve(listener, sender, &stop))?
+        };
+
+        Ok(Self {
+            addr,
+            stop,
+            thread: Some(thread),
+        })
+    }
+
+    /// The address the server is listening on.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Stops the server, waiting for its thread to exit. The responders aren't waited for, they
+    /// exit on their own once the connections they are answering are done.
+    pub fn shutdown(mut self) {
+        self.stop();
+    }
+
+    fn stop(&mut self) {
+        if let Some(thread) = self.thread.take() {
+            self.stop.store(true, Ordering::Release);
+
+            // Wake up the server, which is blocked waiting for connections. The unspecified
+            // address can't be connected to everywhere, so the loopback one is used instead.
+            let mut addr = self.addr;
+            if addr.ip().is_unspecified() {
+                addr.set_ip(match addr {
+                    SocketAddr::V4(_) => Ipv4Addr::LOCALHOST.into(),
+                    SocketAddr::V6(_) => Ipv6Addr::LOCALHOST.into(),
+                });
+            }
+
+            // If the server can't be reached it's left behind, rather than waiting forever.
+            if TcpStream::connect_timeout(&addr, TIMEOUT).is_ok() {
+                let _ = thread.join();
+            }
+        }
+    }
+}
+
+impl Drop for MetricsServer {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+impl fmt::Debug for MetricsServer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MetricsServer")
+            .field("addr", &self.addr)
+            .finish()
+    }
+}
+
+/// Accepts connections until the server is stopped, handing them to the responders. Errors of
+/// single connections are ignored, and connections which find the backlog full are closed.
+fn serve(listener: TcpListener, sender: SyncSender<TcpStream>, stop: &AtomicBool) {
+    for stream in listener.incoming() {
+        if stop.load(Ordering::Acquire) {
+            break;
+        }
+
+        if let Ok(stream) = stream {
+            let _ = sender.try_send(stream);
+        }
+    }
+}
+
+/// Answers the connections accepted by the server until it stops.
+fn answer(receiver: &Mutex<Receiver<TcpStream>>, pools: &[Handle]) {
+    loop {
+        // The lock is released before answering, so the rest of responders can take the next
+        // connections meanwhile.
+        let stream = receiver.lock().recv();
+
+        match stream {
+            Ok(stream) => {
+                let _ = respond(stream, pools);
+            }
+            Err(_) => break,
+        }
+    }
+}
+
+/// Reads the request head and answers with the metrics, closing the connection.
+fn respond(mut stream: TcpStream, pools: &[Handle]) -> io::Result<()> {
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+
+    let mut head = Vec::new();
+    let mut buffer = [0; 1024];
+
+    while !head.windows(4).any(|window| window == b"\r\n\r\n") && head.len() < 8 * 1024 {
+        match stream.read(&mut buffer)? {
+            0 => break,
+            read => head.extend_from_slice(&buffer[..read]),
+        }
+    }
+
+    let body = render(pools);
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        CONTENT_TYPE,
+        body.len(),
+        body
+    )?;
+    stream.flush()?;
+    stream.shutdown(Shutdown::Both)
+}
@@ -0,0 +1,239 @@
+use crate::handle::Handle;
+use crate::shared::Shared;
+use crate::task::{JoinSlot, JoinTask, Steal, TaskMeta, TaskType};
+use parking_lot::Mutex;
+use std::{
+    any::Any,
+    future::poll_fn,
+    marker::PhantomData,
+    panic::{catch_unwind, resume_unwind, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    task::{Poll, Waker},
+};
+
+/// The state of a scope, shared between the scope and its tasks.
+struct ScopeState {
+    /// The number of tasks which didn't finish yet.
+    pending: AtomicUsize,
+    /// The payload of the first panic of the tasks of the scope.
+    panic: Mutex<Option<Box<dyn Any + Send + 'static>>>,
+    /// The waker of the thread waiting for the tasks to finish.
+    waker: Mutex<Option<Waker>>,
+    /// The pool the tasks are spawned into, used to tell why a task was discarded.
+    shared: Arc<Shared>,
+}
+
+impl ScopeState {
+    fn panicked(&self, payload: Box<dyn Any + Send + 'static>) {
+        self.panic.lock().get_or_insert(payload);
+    }
+
+    fn finished(&self) {
+        if self.pending.fetch_sub(1, Ordering::AcqRel) == 1 {
+            if let Some(waker) = self.waker.lock().take() {
+                waker.wake();
+            }
+        }
+    }
+}
+
+/// A task spawned into a scope, which registers its end when dropped, even if it never runs
+/// because the pool shut down.
+struct ScopedTask {
+    state: Arc<ScopeState>,
+    task: Option<Box<dyn FnOnce() + Send + 'static>>,
+}
+
+impl ScopedTask {
+    fn run(mut self) {
+        if let Some(task) = self.task.take() {
+            if let Err(payload) = catch_unwind(AssertUnwindSafe(task)) {
+                self.state.panicked(payload);
+            }
+        }
+    }
+}
+
+impl Drop for ScopedTask {
+    fn drop(&mut self) {
+        // The task must be dropped before the scope is notified, as it may borrow from it.
+        if let Some(task) = self.task.take() {
+            drop(task);
+
+            // Tasks are only discarded by a pool which is still running when the queue is full.
+            let reason = match self.state.shared.is_closed() {
+                true => "Scoped task discarded, thread pool exited.",
+                false => "Scoped task discarded, rejected by the full task queue.",
+            };
+            self.state.panicked(Box::new(reason));
+        }
+
+        self.state.finished();
+    }
+}
+
+/// A scope used to spawn tasks which can borrow data from the stack of the caller, this is
+/// created by [scope](Handle::scope).
+///
+/// Every task spawned into the scope finishes before [scope](Handle::scope) returns.
+pub struct Scope<'scope> {
+    handle: Handle,
+    state: Arc<ScopeState>,
+    /// Makes the scope invariant over its lifetime.
+    _marker: PhantomData<fn(&'scope ()) -> &'scope ()>,
+}
+
+impl<'scope> Scope<'scope> {
+    fn new(handle: Handle) -> Self {
+        Self {
+            state: Arc::new(ScopeState {
+                pending: AtomicUsize::new(0),
+                panic: Mutex::new(None),
+                waker: Mutex::new(None),
+                shared: Arc::clone(&handle.shared),
+            }),
+            handle,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Spawns a task into the scope, the task can borrow anything which outlives the scope.
+    ///
+    /// If the task panics, the panic is propagated by [scope](Handle::scope) once every task
+    /// of the scope finishes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the task can't be spawned, like [spawn](Handle::spawn).
+    #[track_caller]
+    pub fn spawn<F>(&self, task: F)
+    where
+        F: FnOnce() + Send + 'scope,
+    {
+        let task: Box<dyn FnOnce() + Send + 'scope> = Box::new(task);
+        // SAFETY: The scope waits for every task to finish or be dropped before returning, so
+        // nothing borrowed by the task is used after it's gone.
+        let task: Box<dyn FnOnce() + Send + 'static> = unsafe { std::mem::transmute(task) };
+
+        self.state.pending.fetch_add(1, Ordering::AcqRel);
+        let task = ScopedTask {
+            state: Arc::clone(&self.state),
+            task: Some(task),
+        };
+
+        self.handle.spawn_detached(move || task.run());
+    }
+
+    /// Waits until every task of the scope finishes, workers of the pool keep running queued
+    /// tasks while waiting.
+    fn wait(&self) {
+        let wait = poll_fn(|cx| {
+            let mut waker = self.state.waker.lock();
+
+            if self.state.pending.load(Ordering::Acquire) == 0 {
+                Poll::Ready(())
+            } else {
+                *waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        });
+
+        self.handle.block_on(wait);
+    }
+}
+
+/// Creates a [scope](Scope) in the given pool and runs the function with it, waiting for every
+/// task spawned into it before returning.
+pub(crate) fn scope<'scope, F, R>(handle: &Handle, fun: F) -> R
+where
+    F: FnOnce(&Scope<'scope>) -> R,
+{
+    let scope = Scope::new(handle.clone());
+    let value = catch_unwind(AssertUnwindSafe(|| fun(&scope)));
+
+    // The tasks must finish even if the function panicked, as they may borrow from its caller.
+    scope.wait();
+
+    let panic = scope.state.panic.lock().take();
+
+    match (value, panic) {
+        (Err(payload), _) | (Ok(_), Some(payload)) => resume_unwind(payload),
+        (Ok(value), None) => value,
+    }
+}
+
+/// The function of a join which idle workers can steal, it lives on the stack of the joining
+/// thread while the queued task only holds a type erased pointer to it.
+struct JoinB<B, RB> {
+    /// The function, taken by whoever runs it.
+    oper: Mutex<Option<B>>,
+    /// The output of the function once a worker which stole it finishes running it.
+    output: Mutex<Option<std::thread::Result<RB>>>,
+}
+
+impl<B, RB> Steal for JoinB<B, RB>
+where
+    B: FnOnce() -> RB + Send,
+    RB: Send,
+{
+    fn steal(&self) -> bool {
+        let oper = self.oper.lock().take().expect("Join function ran twice");
+        let output = catch_unwind(AssertUnwindSafe(oper));
+        let panicked = output.is_err();
+        *self.output.lock() = Some(output);
+        panicked
+    }
+}
+
+/// Runs both functions, potentially in parallel, returning their outputs. The first one runs on
+/// the current thread, while the second one is queued so idle workers can steal it, if no worker
+/// took it by the time the first one finishes, it runs on the current thread too.
+///
+/// The queued task skips the capacity of the queue and the rejection policy, as the second
+/// function can always run on the current thread. Once it ran here, the queued task gives its
+/// place in the queue back and is dropped without running once a worker finds it.
+pub(crate) fn join<A, B, RA, RB>(handle: &Handle, oper_a: A, oper_b: B) -> (RA, RB)
+where
+    A: FnOnce() -> RA + Send,
+    B: FnOnce() -> RB + Send,
+    RA: Send,
+    RB: Send,
+{
+    let job = JoinB {
+        oper: Mutex::new(Some(oper_b)),
+        output: Mutex::new(None),
+    };
+    // SAFETY: The function is either reclaimed below or waited for until the worker which took
+    // it is done with it, nothing here unwinds in between as both functions run inside
+    // `catch_unwind`. Once `join` returns the queued task only holds the slot, which is
+    // `'static`.
+    let slot = unsafe { JoinSlot::new(&job) };
+    let task = JoinTask::new(Arc::clone(&slot), TaskMeta::new(None));
+
+    // If the pool is closed the task is given back and dropped, so the function runs here.
+    if handle.shared.schedule(TaskType::Join(task)).is_err() {
+        slot.dequeue();
+    }
+
+    let output_a = catch_unwind(AssertUnwindSafe(oper_a));
+
+    // A worker took the function, wait for it to finish, unless it gives the function back
+    // without running it, which happens when the task is discarded or rejected.
+    let stolen = !slot.reclaim(&handle.shared) && handle.block_on(poll_fn(|cx| slot.poll_done(cx)));
+
+    let output_b = match stolen {
+        true => job.output.lock().take().expect("Stolen join function gave no output"),
+        false => {
+            let oper_b = job.oper.lock().take().expect("Join function ran twice");
+            catch_unwind(AssertUnwindSafe(oper_b))
+        }
+    };
+
+    match (output_a, output_b) {
+        (Err(payload), _) | (Ok(_), Err(payload)) => resume_unwind(payload),
+        (Ok(output_a), Ok(output_b)) => (output_a, output_b),
+    }
+}
@@ -1,75 +1,714 @@
-use crate::{task::{TaskType}, worker::WorkerAction};
-use parking_lot::{Condvar, Mutex};
-use std::{
-    collections::VecDeque,
-    sync::{
-        atomic::{AtomicBool, Ordering},
-        Arc,
-    },
+use crate::{
+    builder::{PanicPolicy, RejectionPolicy},
+    error::SpawnError,
+    metrics::{Counters, PoolMetrics, WorkerStats},
+    panic::TaskPanic,
+    task::{Priority, TaskType},
+    timer::TimerHandle,
+    worker::{Worker, WorkerAction, WorkerConfig},
 };
+use crossbeam_deque::{Injector, Steal, Stealer, Worker as LocalQueue};
+use parking_lot::{Condvar, Mutex, RwLock};
+use std::collections::VecDeque;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::{
+    atomic::{fence, AtomicBool, AtomicUsize, Ordering},
+    Arc, Weak,
+};
+use std::thread::JoinHandle as StdThreadJoinHandle;
+use std::time::{Duration, Instant};
+
+/// Every how many tasks a worker looks for low priority tasks first, must be a power of two.
+const LOW_PRIORITY_INTERVAL: usize = 32;
+/// Every how many tasks a worker looks for normal priority tasks first, must be a power of two.
+const NORMAL_PRIORITY_INTERVAL: usize = 8;
+
+/// Retries a steal operation until it either succeeds or finds the queue empty.
+fn retry<T>(mut steal: impl FnMut() -> Steal<T>) -> Option<T> {
+    std::iter::repeat_with(&mut steal)
+        .find(|steal| !steal.is_retry())
+        .and_then(Steal::success)
+}
 
 /// The shared data for all workers in the thread pool.
 pub struct Shared {
-    /// Queue of tasks.
-    pub queue: Mutex<VecDeque<TaskType>>,
+    /// Global queues, one per priority, tasks spawned from outside the pool and the ones with
+    /// non normal priorities are pushed here.
+    injectors: [Injector<TaskType>; 3],
+    /// The stealers of the local queues of every worker, used to steal work from siblings, by
+    /// worker index. Slots of retired workers are empty until a new worker takes them.
+    stealers: RwLock<Vec<Option<Stealer<TaskType>>>>,
     /// The variable used by worker threads to wait for notifications.
-    pub condvar: Condvar,
+    condvar: Condvar,
     /// The lock used along with the upper condvar.
     lock: Mutex<()>,
+    /// The number of workers currently waiting on the condvar.
+    sleeping: AtomicUsize,
     /// Whether the workers should stop and exit.
     pub exit: AtomicBool,
+    /// Whether the pool stopped accepting tasks, workers exit once there is no work left.
+    pub closed: AtomicBool,
+    /// Whether the pool was shut down because of a panic, see [PanicPolicy](PanicPolicy).
+    panic_shutdown: AtomicBool,
+    /// The number of spawns in progress, used to close the pool without losing tasks.
+    spawning: AtomicUsize,
+    /// The counters of the tasks of the pool.
+    pub counters: Counters,
+    /// The worker threads of the pool.
+    workers: Mutex<Workers>,
+    /// The number of workers taking tasks, this doesn't include retired workers which are
+    /// still exiting.
+    threads: AtomicUsize,
+    /// The minimum number of workers, idle workers aren't retired below this.
+    min_threads: AtomicUsize,
+    /// The maximum number of workers, new workers are spawned up to this when the queue backs up.
+    max_threads: AtomicUsize,
+    /// How long extra workers can stay idle before being retired.
+    keep_alive: Duration,
+    /// The configuration used to spawn new workers.
+    config: WorkerConfig,
+    /// The name of the pool.
+    name: String,
+    /// The timer which queues the delayed and periodic tasks, [None](None) if disabled.
+    timer: Option<TimerHandle>,
+    /// A reference to itself, given to the spawned workers.
+    this: Weak<Shared>,
+    /// The variable used to notify when every worker exited.
+    exited: Condvar,
+    /// The number of tasks waiting in the queues.
+    queued: AtomicUsize,
+    /// The maximum number of tasks waiting in the queues, [None](None) if unbounded.
+    capacity: Option<usize>,
+    /// What to do when spawning into a full queue.
+    pub policy: RejectionPolicy,
+    /// The number of spawns blocked waiting for space in the queue.
+    blocked: AtomicUsize,
+    /// The lock used along with the condvar below.
+    space_lock: Mutex<()>,
+    /// The variable used by blocked spawns to wait for space in the queue.
+    space: Condvar,
+}
+
+/// The worker threads of the pool.
+struct Workers {
+    /// The number of worker threads which didn't exit yet.
+    live: usize,
+    /// The join handles of the worker threads, retired workers remove their own.
+    handles: VecDeque<StdThreadJoinHandle<()>>,
+    /// The statistics of the workers, by index, slots of retired workers are empty.
+    stats: Vec<Option<Arc<WorkerStats>>>,
+}
+
+/// The sizing options of the pool.
+pub struct PoolSize {
+    pub min_threads: usize,
+    pub max_threads: usize,
+    pub keep_alive: Duration,
+}
+
+/// What to do with a task being spawned, see [reserve](Shared::reserve).
+pub enum Reservation {
+    /// There is space in the queue for the task.
+    Queue,
+    /// The task must run on the spawning thread.
+    RunInline,
+    /// The queue is full and the task was rejected.
+    Full,
+    /// The pool was closed while waiting for space in the queue.
+    Closed,
 }
 
 impl Shared {
-    pub fn new() -> Arc<Self> {
-        Arc::new(Self {
-            queue: Mutex::new(VecDeque::new()),
+    pub fn new(
+        name: String,
+        timer: Option<TimerHandle>,
+        config: WorkerConfig,
+        size: PoolSize,
+        capacity: Option<usize>,
+        policy: RejectionPolicy
+    ) -> Arc<Self> {
+        Arc::new_cyclic(|this| Self {
+            workers: Mutex::new(Workers {
+                live: 0,
+                handles: VecDeque::new(),
+                stats: Vec::new(),
+            }),
+            threads: AtomicUsize::new(0),
+            min_threads: AtomicUsize::new(size.min_threads),
+            max_threads: AtomicUsize::new(size.max_threads),
+            keep_alive: size.keep_alive,
+            config,
+            name,
+            timer,
+            this: this.clone(),
+            injectors: [Injector::new(), Injector::new(), Injector::new()],
+            stealers: RwLock::new(Vec::new()),
             condvar: Condvar::new(),
             lock: Mutex::new(()),
+            sleeping: AtomicUsize::new(0),
             exit: AtomicBool::new(false),
+            closed: AtomicBool::new(false),
+            panic_shutdown: AtomicBool::new(false),
+            spawning: AtomicUsize::new(0),
+            counters: Counters::default(),
+            exited: Condvar::new(),
+            queued: AtomicUsize::new(0),
+            capacity,
+            policy,
+            blocked: AtomicUsize::new(0),
+            space_lock: Mutex::new(()),
+            space: Condvar::new(),
         })
     }
 
-    fn try_get(&self) -> Option<TaskType> {
-        self.queue.try_lock().map(|mut q| q.pop_front()).flatten()
-    }
-
     pub fn should_exit(&self) -> bool {
         self.exit.load(Ordering::Relaxed)
     }
 
-    pub fn wait(&self) -> WorkerAction {
-        if self.should_exit() {
-            WorkerAction::Exit
+    /// Whether the pool doesn't accept new tasks anymore.
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst) || self.should_exit()
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn timer(&self) -> Option<&TimerHandle> {
+        self.timer.as_ref()
+    }
+
+    /// Stops the timer, dropping the delayed and periodic tasks waiting in it, returning how
+    /// many delayed tasks were dropped.
+    pub fn shutdown_timer(&self) -> usize {
+        self.timer.as_ref().map_or(0, TimerHandle::shutdown)
+    }
+
+    pub fn config(&self) -> &WorkerConfig {
+        &self.config
+    }
+
+    /// The number of workers of the pool.
+    pub fn thread_count(&self) -> usize {
+        self.threads.load(Ordering::SeqCst)
+    }
+
+    /// Spawns the minimum number of workers of the pool.
+    pub fn start(&self) -> std::io::Result<()> {
+        let mut workers = self.workers.lock();
+
+        for _ in 0..self.min_threads.load(Ordering::Relaxed) {
+            self.spawn_worker(&mut workers)?;
+        }
+
+        Ok(())
+    }
+
+    /// Spawns a new worker, giving it the first free slot for its local queue.
+    fn spawn_worker(&self, workers: &mut Workers) -> std::io::Result<()> {
+        let shared = self.this.upgrade().expect("Thread pool dropped");
+        let queue = LocalQueue::new_fifo();
+
+        let index = {
+            let mut stealers = self.stealers.write();
+            let index = stealers.iter().position(Option::is_none).unwrap_or(stealers.len());
+
+            if index == stealers.len() {
+                stealers.push(None);
+            }
+
+            stealers[index] = Some(queue.stealer());
+            index
+        };
+
+        let stats = Arc::new(WorkerStats::default());
+
+        match Worker::new(shared, index, Arc::clone(&stats)).spawn(queue) {
+            Ok(handle) => {
+                if index >= workers.stats.len() {
+                    workers.stats.resize(index + 1, None);
+                }
+
+                workers.stats[index] = Some(stats);
+                workers.handles.push_back(handle);
+                workers.live += 1;
+                self.threads.fetch_add(1, Ordering::SeqCst);
+                Ok(())
+            }
+            Err(error) => {
+                self.stealers.write()[index] = None;
+                Err(error)
+            }
+        }
+    }
+
+    /// Spawns a new worker if there are tasks waiting and the pool can grow.
+    fn grow(&self) {
+        let max = self.max_threads.load(Ordering::Relaxed);
+
+        if self.thread_count() >= max || self.is_closed() || self.is_empty() {
+            return;
+        }
+
+        let mut workers = self.workers.lock();
+
+        // If the worker can't be spawned, the task will be ran by one of the existing workers.
+        if self.thread_count() < max && !self.is_closed() {
+            let _ = self.spawn_worker(&mut workers);
+        }
+    }
+
+    /// Retires the worker with the given index if the pool has more workers than its maximum,
+    /// or if the worker is idle and the pool has more workers than its minimum with no tasks
+    /// waiting, returning whether it was retired.
+    fn retire(&self, index: usize, idle: bool) -> bool {
+        let mut workers = self.workers.lock();
+        let threads = self.thread_count();
+        let retire = threads > self.max_threads.load(Ordering::Relaxed)
+            || (idle && threads > self.min_threads.load(Ordering::Relaxed) && self.is_empty());
+
+        if !retire {
+            return false;
+        }
+
+        self.threads.fetch_sub(1, Ordering::SeqCst);
+        self.stealers.write()[index] = None;
+        workers.stats[index] = None;
+
+        // The thread is exiting by itself, so there is no need to join it.
+        let current = std::thread::current().id();
+        workers.handles.retain(|handle| handle.thread().id() != current);
+
+        true
+    }
+
+    /// Sets the number of workers of the pool, spawning the missing ones right away, while the
+    /// extra ones exit once they finish their current task.
+    pub fn set_thread_count(&self, threads: usize) -> std::io::Result<()> {
+        let threads = threads.max(1);
+        self.min_threads.store(threads, Ordering::Relaxed);
+        self.max_threads.store(threads, Ordering::Relaxed);
+
+        {
+            let mut workers = self.workers.lock();
+
+            while self.thread_count() < threads && !self.is_closed() {
+                self.spawn_worker(&mut workers)?;
+            }
+        }
+
+        // Wake up the sleeping workers, so the extra ones see they have to exit.
+        self.notify_all();
+        Ok(())
+    }
+
+    /// Takes a snapshot of the metrics of the pool.
+    pub fn metrics(&self) -> PoolMetrics {
+        let workers = self.workers.lock();
+        let stats = workers.stats
+            .iter()
+            .enumerate()
+            .filter_map(|(index, stats)| Some((index, &**stats.as_ref()?)));
+
+        crate::metrics::snapshot(&self.counters, self.queued.load(Ordering::SeqCst), stats)
+    }
+
+    /// Registers the exit of a worker.
+    pub fn worker_exited(&self) {
+        let mut workers = self.workers.lock();
+        workers.live -= 1;
+
+        if workers.live == 0 {
+            self.exited.notify_all();
+
+            // Nobody may ever shut down a pool which was shut down because of a panic, so the
+            // last worker discards the remaining tasks instead.
+            if self.panic_shutdown.load(Ordering::SeqCst) {
+                drop(workers);
+
+                while let Some(task) = self.pop_global() {
+                    task.discard();
+                }
+            }
+        }
+    }
+
+    /// Reports the panic of a task to the panic handler of the pool, then follows the panic
+    /// policy. Panics of the handler itself are ignored.
+    pub fn panicked(&self, panic: &TaskPanic<'_>) {
+        if let Some(handler) = &self.config.panic_handler {
+            let _ = catch_unwind(AssertUnwindSafe(|| handler(panic)));
+        }
+
+        match self.config.panic_policy {
+            PanicPolicy::Continue => (),
+            PanicPolicy::Abort => std::process::abort(),
+            PanicPolicy::Shutdown => {
+                if !self.panic_shutdown.swap(true, Ordering::SeqCst) {
+                    self.close();
+                    self.exit.store(true, Ordering::Relaxed);
+                    self.notify_all();
+
+                    // The timer isn't waited for, as it may be queueing a task into this pool.
+                    if let Some(timer) = &self.timer {
+                        timer.stop();
+                    }
+                }
+            }
+        }
+    }
+
+    /// Waits until every worker exits or the deadline is reached, returning whether all of
+    /// them exited.
+    pub fn wait_workers(&self, deadline: Instant) -> bool {
+        let mut workers = self.workers.lock();
+
+        while workers.live > 0 {
+            if self.exited.wait_until(&mut workers, deadline).timed_out() {
+                return workers.live == 0;
+            }
+        }
+
+        true
+    }
+
+    /// Joins every worker thread, waiting as well for the retired ones which are still
+    /// exiting.
+    pub fn join_workers(&self) {
+        let handles = std::mem::take(&mut self.workers.lock().handles);
+
+        for handle in handles {
+            handle.join().expect("Failed to join thread");
+        }
+
+        let mut workers = self.workers.lock();
+
+        while workers.live > 0 {
+            self.exited.wait(&mut workers);
+        }
+    }
+
+    /// Whether there are no tasks neither in the global queues nor in any of the local ones.
+    fn is_empty(&self) -> bool {
+        self.injectors.iter().all(Injector::is_empty)
+            && self.stealers.read().iter().flatten().all(Stealer::is_empty)
+    }
+
+    /// Looks for a task to run, higher priorities are looked first, but every once in a while
+    /// the lower ones are looked first instead, so their tasks don't starve.
+    pub fn find_task(
+        &self,
+        local: &LocalQueue<TaskType>,
+        index: usize,
+        tick: usize
+    ) -> Option<TaskType> {
+        let order = if tick & (LOW_PRIORITY_INTERVAL - 1) == 0 {
+            [Priority::Low, Priority::Normal, Priority::High]
+        } else if tick & (NORMAL_PRIORITY_INTERVAL - 1) == 0 {
+            [Priority::Normal, Priority::High, Priority::Low]
         } else {
-            if let Some(task) = self.try_get() {
-                WorkerAction::Run(task)
-            } else {
-                let mut lock = self.lock.lock();
-                self.condvar.wait(&mut lock);
-                if self.should_exit() {
-                    WorkerAction::Exit
-                } else {
-                    match self.queue.lock().pop_front() {
-                        Some(task) => WorkerAction::Run(task),
-                        None => {
-                            // If we got here, we got unparked and the task was not stored yet, so
-                            // just notify another thread and wait again.
-                            self.condvar.notify_one();
-                            WorkerAction::Retry
-                        }
+            Priority::ALL
+        };
+
+        loop {
+            let task = order
+                .into_iter()
+                .find_map(|priority| self.find_with_priority(local, index, priority))?;
+
+            if let Some(task) = self.taken(task) {
+                return Some(task);
+            }
+        }
+    }
+
+    /// Looks for a task with the given priority. Normal priority tasks are looked first in the
+    /// local queue of the worker, then in the global queue and lastly trying to steal from the
+    /// rest of the workers, starting by the one next to the given index so not every worker
+    /// tries to steal from the same sibling.
+    fn find_with_priority(
+        &self,
+        local: &LocalQueue<TaskType>,
+        index: usize,
+        priority: Priority
+    ) -> Option<TaskType> {
+        let injector = &self.injectors[priority.index()];
+
+        match priority {
+            Priority::Normal => local.pop().or_else(|| {
+                retry(|| {
+                    injector.steal_batch_and_pop(local).or_else(|| {
+                        let stealers = self.stealers.read();
+                        let (after, before) = stealers.split_at(index + 1);
+                        after.iter().chain(before).flatten().map(Stealer::steal).collect()
+                    })
+                })
+            }),
+            // Tasks with other priorities are taken one by one, so they are spread between the
+            // workers instead of being moved in batches into a single local queue.
+            _ => retry(|| injector.steal()),
+        }
+    }
+
+    /// Takes the first task of the global queues, starting by the highest priority.
+    pub fn pop_global(&self) -> Option<TaskType> {
+        loop {
+            let task = self.injectors
+                .iter()
+                .find_map(|injector| retry(|| injector.steal()))?;
+
+            if let Some(task) = self.taken(task) {
+                return Some(task);
+            }
+        }
+    }
+
+    /// Takes the oldest task with the lowest priority, looking first in the global queues and
+    /// then in the local ones.
+    fn pop_oldest(&self) -> Option<TaskType> {
+        loop {
+            let task = self.injectors
+                .iter()
+                .rev()
+                .find_map(|injector| retry(|| injector.steal()))
+                .or_else(|| {
+                    let stealers = self.stealers.read();
+                    retry(|| stealers.iter().flatten().map(Stealer::steal).collect())
+                })?;
+
+            if let Some(task) = self.taken(task) {
+                return Some(task);
+            }
+        }
+    }
+
+    /// Pushes a task into the global queue of its priority, without registering it as a new
+    /// task in the queue, used by workers to give back the tasks left in their local queues.
+    pub fn push_global(&self, task: TaskType) {
+        self.injectors[task.priority().index()].push(task);
+    }
+
+    /// Registers a task being taken out of the queues, making room for blocked spawns. Join
+    /// tasks whose function was taken back by the joining thread already gave their place back,
+    /// they are dropped here instead of being returned.
+    fn taken(&self, mut task: TaskType) -> Option<TaskType> {
+        let (release, stale) = match &mut task {
+            TaskType::Join(task) => (task.slot().dequeue(), !task.claim()),
+            _ => (true, false),
+        };
+
+        if release {
+            self.released();
+        }
+
+        (!stale).then_some(task)
+    }
+
+    /// Gives back the place of a task in the queue, making room for blocked spawns.
+    pub fn released(&self) {
+        self.queued.fetch_sub(1, Ordering::SeqCst);
+        // Pairs with the fence in `wait_space`.
+        fence(Ordering::SeqCst);
+
+        if self.blocked.load(Ordering::SeqCst) > 0 {
+            let _lock = self.space_lock.lock();
+            self.space.notify_one();
+        }
+    }
+
+    /// Tries to reserve a place in the queue for a new task.
+    fn try_reserve(&self, capacity: usize) -> bool {
+        self.queued
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |queued| {
+                (queued < capacity).then(|| queued + 1)
+            })
+            .is_ok()
+    }
+
+    /// Waits until there may be space in the queue, returns false if the pool got closed.
+    fn wait_space(&self, capacity: usize) -> bool {
+        let mut lock = self.space_lock.lock();
+        self.blocked.fetch_add(1, Ordering::SeqCst);
+        fence(Ordering::SeqCst);
+
+        if !self.is_closed() && self.queued.load(Ordering::SeqCst) >= capacity {
+            self.space.wait(&mut lock);
+        }
+
+        self.blocked.fetch_sub(1, Ordering::SeqCst);
+        !self.is_closed()
+    }
+
+    /// Reserves a place in the queue for a new task, following the rejection policy of the
+    /// pool if the queue is full.
+    pub fn reserve(&self) -> Reservation {
+        let capacity = match self.capacity {
+            Some(capacity) => capacity,
+            None => {
+                self.queued.fetch_add(1, Ordering::SeqCst);
+                return Reservation::Queue;
+            }
+        };
+
+        loop {
+            if self.try_reserve(capacity) {
+                return Reservation::Queue;
+            }
+
+            match self.policy {
+                // Blocking a worker could leave the pool without threads to empty the queue.
+                RejectionPolicy::Block if crate::worker::is_worker_of(self) => {
+                    return Reservation::RunInline
+                }
+                RejectionPolicy::Block => {
+                    if !self.wait_space(capacity) {
+                        return Reservation::Closed;
+                    }
+                }
+                RejectionPolicy::Error | RejectionPolicy::DropNew => return Reservation::Full,
+                RejectionPolicy::CallerRuns => return Reservation::RunInline,
+                RejectionPolicy::DropOldest => {
+                    if let Some(task) = self.pop_oldest() {
+                        task.reject();
                     }
                 }
             }
         }
     }
 
-    pub fn schedule(&self, task: TaskType) {
-        if self.should_exit() {
-            panic!("Cannot spawn a task, thread pool exited.");
+    pub fn wait(&self, local: &LocalQueue<TaskType>, index: usize, tick: usize) -> WorkerAction {
+        loop {
+            if self.should_exit() {
+                return WorkerAction::Exit;
+            }
+
+            if self.thread_count() > self.max_threads.load(Ordering::Relaxed)
+                && self.retire(index, false)
+            {
+                return WorkerAction::Exit;
+            }
+
+            if let Some(task) = self.find_task(local, index, tick) {
+                return WorkerAction::Run(task);
+            }
+
+            // The pool is closed and there is no work left, so there is nothing else to do.
+            if self.is_closed() {
+                return WorkerAction::Exit;
+            }
+
+            let mut lock = self.lock.lock();
+            self.sleeping.fetch_add(1, Ordering::SeqCst);
+            // Pairs with the fence in `notify`, either we see the pushed task or the scheduler
+            // sees us sleeping and notifies us.
+            fence(Ordering::SeqCst);
+
+            // Extra workers only wait for the keep alive time, retiring if they get no work.
+            let mut timed_out = false;
+
+            if !self.is_closed() && self.is_empty() {
+                if self.thread_count() > self.min_threads.load(Ordering::Relaxed) {
+                    timed_out = self.condvar.wait_for(&mut lock, self.keep_alive).timed_out();
+                } else {
+                    self.condvar.wait(&mut lock);
+                }
+            }
+
+            self.sleeping.fetch_sub(1, Ordering::SeqCst);
+            drop(lock);
+
+            if timed_out && self.retire(index, true) {
+                return WorkerAction::Exit;
+            }
+        }
+    }
+
+    /// Wakes up a sleeping worker, if any, otherwise spawns a new one if the pool can grow.
+    pub fn notify(&self) {
+        fence(Ordering::SeqCst);
+
+        if self.sleeping.load(Ordering::SeqCst) > 0 {
+            let _lock = self.lock.lock();
+            self.condvar.notify_one();
+        } else {
+            self.grow();
+        }
+    }
+
+    /// Wakes up all workers, used when the pool shuts down.
+    pub fn notify_all(&self) {
+        let _lock = self.lock.lock();
+        self.condvar.notify_all();
+    }
+
+    /// Stops accepting new tasks, waiting for the spawns in progress to finish so no task gets
+    /// pushed after this returns.
+    pub fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+
+        // Wake up the spawns blocked on a full queue, so they see the pool closed.
+        {
+            let _lock = self.space_lock.lock();
+            self.space.notify_all();
+        }
+
+        while self.spawning.load(Ordering::SeqCst) > 0 {
+            std::thread::yield_now();
+        }
+
+        self.notify_all();
+    }
+
+    /// Registers a spawn in progress, failing if the pool is closed.
+    pub fn enter(&self) -> Result<SpawnGuard<'_>, SpawnError<()>> {
+        self.spawning.fetch_add(1, Ordering::SeqCst);
+        let guard = SpawnGuard(self);
+
+        if self.is_closed() {
+            Err(SpawnError::Shutdown(()))
+        } else {
+            Ok(guard)
+        }
+    }
+
+    /// Pushes a task into the queues, the guard ensures the pool doesn't get closed meanwhile.
+    ///
+    /// A place in the queue must have been [reserved](Self::reserve) for the task before.
+    pub fn push(&self, _guard: &SpawnGuard<'_>, mut task: TaskType) {
+        task.set_queued_at(Instant::now());
+
+        // Normal priority tasks spawned from a worker of this same pool go into its local queue,
+        // the rest of them go into the global queue of their priority.
+        let task = match task.priority() {
+            Priority::Normal => crate::worker::push_local(self, task).err(),
+            _ => Some(task),
+        };
+
+        if let Some(task) = task {
+            self.push_global(task);
+        }
+
+        self.notify();
+    }
+
+    /// Schedules a task, giving it back if the pool is closed. This is used for tasks which
+    /// were already accepted by the pool, so the capacity of the queue isn't enforced.
+    pub fn schedule(&self, task: TaskType) -> Result<(), TaskType> {
+        match self.enter() {
+            Ok(guard) => {
+                self.queued.fetch_add(1, Ordering::SeqCst);
+                self.push(&guard, task);
+                Ok(())
+            }
+            Err(_) => Err(task),
         }
+    }
+}
+
+/// A guard which keeps the pool from being closed while a task is being spawned.
+pub struct SpawnGuard<'a>(&'a Shared);
 
-        self.queue.lock().push_back(task);
-        self.condvar.notify_one();
+impl Drop for SpawnGuard<'_> {
+    fn drop(&mut self) {
+        self.0.spawning.fetch_sub(1, Ordering::SeqCst);
     }
 }
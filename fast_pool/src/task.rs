@@ -1,9 +1,25 @@
-use crate::channel::ChannelHalf;
+use crate::channel::{Cancel, ChannelHalf};
+use crate::join::JoinError;
+use parking_lot::Mutex;
+use std::any::Any;
+use std::future::Future;
 use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering};
 use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
 use std::time::{Duration, Instant};
+use crate::hook::{TaskInfo, TaskKind};
+use crate::panic::TaskPanic;
+use crate::periodic::PeriodicState;
 use crate::shared::Shared;
 
+/// Called with the payload of a panic of the task being ran, before the panic is given to its
+/// handle.
+type PanicFn<'a> = &'a dyn Fn(&(dyn Any + Send));
+/// The type erased function of a synchronous task.
+type SyncFun = Box<dyn FnOnce(PanicFn<'_>) -> Outcome + Send + 'static>;
+
 /// A synchronous task, any type implementing this trait can be ran inside the thread pool.
 pub trait Task: Send + 'static
 {
@@ -24,93 +40,811 @@ where
     }
 }
 
+/// The priority of a task, workers always prefer tasks with higher priorities, although tasks
+/// with lower priorities are ran every once in a while so they don't starve.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Priority {
+    /// Tasks which should run as soon as possible, like latency sensitive ones.
+    High,
+    /// The priority tasks are spawned with by default.
+    #[default]
+    Normal,
+    /// Tasks which can wait, like bulk background work.
+    Low,
+}
+
+impl Priority {
+    /// All priorities, from the highest to the lowest.
+    pub(crate) const ALL: [Priority; 3] = [Priority::High, Priority::Normal, Priority::Low];
+
+    pub(crate) fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// The data identifying a task, shared by all of its runs.
+pub struct TaskMeta {
+    /// The unique id of the task.
+    pub id: u64,
+    /// The name of the task, if it was given one.
+    name: Option<Arc<str>>,
+    /// The tracing data of the task, boxed to keep the tasks small.
+    #[cfg(feature = "tracing")]
+    trace: Box<Trace>,
+}
+
+#[cfg(feature = "tracing")]
+struct Trace {
+    /// Where the task was spawned.
+    location: &'static std::panic::Location<'static>,
+    /// The span which was current when the task was spawned, the spans of its runs are
+    /// children of it.
+    parent: tracing::Span,
+}
+
+impl TaskMeta {
+    /// Creates the data of a new task, capturing the spawn site and the current span when the
+    /// `tracing` feature is enabled.
+    #[track_caller]
+    pub fn new(name: Option<&str>) -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+        Self {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            name: name.map(Arc::from),
+            #[cfg(feature = "tracing")]
+            trace: Box::new(Trace {
+                location: std::panic::Location::caller(),
+                parent: tracing::Span::current(),
+            }),
+        }
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Creates the span of a run of the task, the run time is recorded once it finishes.
+    #[cfg(feature = "tracing")]
+    pub fn span(&self, pool: &str, worker: usize, wait: Duration) -> tracing::Span {
+        tracing::info_span!(
+            parent: &self.trace.parent,
+            "task",
+            task.id = self.id,
+            pool,
+            worker,
+            spawned_at = %self.trace.location,
+            queue_wait = ?wait,
+            run_time = tracing::field::Empty,
+        )
+    }
+}
+
+/// How a run of a task ended.
+pub enum Outcome {
+    /// The task ran to completion.
+    Completed,
+    /// The task panicked.
+    Panicked,
+    /// The task was cancelled before it started running.
+    Cancelled,
+    /// The asynchronous task is waiting to be woken.
+    Pending,
+}
+
 pub enum TaskType {
     Sync(SyncTask),
-    Periodic(PeriodicTask)
+    Periodic(PeriodicTask),
+    Async(AsyncTask),
+    Join(JoinTask)
 }
 
 impl TaskType {
-    pub fn run(self) {
+    pub fn priority(&self) -> Priority {
+        match self {
+            Self::Sync(task) => task.priority,
+            Self::Periodic(task) => task.priority,
+            Self::Async(task) => task.inner.priority,
+            Self::Join(_) => Priority::Normal
+        }
+    }
+
+    pub fn meta(&self) -> &TaskMeta {
+        match self {
+            Self::Sync(task) => &task.meta,
+            Self::Periodic(task) => &task.meta,
+            Self::Async(task) => &task.inner.meta,
+            Self::Join(task) => &task.meta
+        }
+    }
+
+    pub fn kind(&self) -> TaskKind {
+        match self {
+            Self::Sync(_) => TaskKind::Sync,
+            Self::Periodic(_) => TaskKind::Periodic,
+            Self::Async(_) => TaskKind::Async,
+            Self::Join(_) => TaskKind::Sync
+        }
+    }
+
+    /// Describes the task to the hooks of the pool, the time it ran for is set once it ran.
+    pub fn info(&self) -> TaskInfo {
+        let meta = self.meta();
+
+        TaskInfo {
+            id: meta.id,
+            name: meta.name.clone(),
+            kind: self.kind(),
+            queued_at: self.queued_at(),
+            elapsed: None,
+        }
+    }
+
+    /// The last time the task was pushed into the queues.
+    pub fn queued_at(&self) -> Instant {
+        match self {
+            Self::Sync(task) => task.queued_at,
+            Self::Periodic(task) => task.queued_at,
+            Self::Async(task) => task.queued_at,
+            Self::Join(task) => task.queued_at
+        }
+    }
+
+    pub fn set_queued_at(&mut self, at: Instant) {
         match self {
-            Self::Sync(task) => (task.fun)(),
-            Self::Periodic(task) => task.run()
+            Self::Sync(task) => task.queued_at = at,
+            Self::Periodic(task) => task.queued_at = at,
+            Self::Async(task) => task.queued_at = at,
+            Self::Join(task) => task.queued_at = at
+        }
+    }
+
+    /// Runs the task, panics are reported to the pool along with the index of the worker
+    /// running it, [None](None) when ran on the spawning thread.
+    pub fn run(self, shared: &Shared, worker: Option<usize>) -> Outcome {
+        match self {
+            Self::Sync(task) => task.run(shared, worker),
+            Self::Periodic(task) => task.run(shared, worker),
+            Self::Async(task) => task.run(shared, worker),
+            Self::Join(task) => task.run(shared)
+        }
+    }
+
+    /// Rejects the task because the queue is full, periodic tasks just skip this run and join
+    /// tasks give their function back to the joining thread.
+    pub fn reject(self) {
+        match self {
+            Self::Sync(task) => task.fail(JoinError::rejected()),
+            Self::Periodic(task) => task.skip(),
+            Self::Async(task) => task.fail(JoinError::rejected()),
+            Self::Join(task) => drop(task)
+        }
+    }
+
+    /// Discards the task because the pool shut down before it could run.
+    pub fn discard(self) {
+        match self {
+            Self::Sync(task) => drop(task),
+            Self::Periodic(task) => drop(task),
+            Self::Async(task) => task.fail(JoinError::shutdown()),
+            Self::Join(task) => drop(task)
         }
     }
 }
 
 pub struct SyncTask {
-    fun: Box<dyn FnOnce() + Send + 'static>,
+    fun: Option<SyncFun>,
+    priority: Priority,
+    /// The channel used to send the output, used to notify the handle if the task never runs.
+    channel: Option<Arc<dyn Cancel>>,
+    meta: TaskMeta,
+    queued_at: Instant,
 }
 
 impl SyncTask {
-    pub fn new<R>(channel: Option<ChannelHalf<R>>, fun: impl Task<Output = R>) -> Self
+    pub fn new<R>(
+        channel: Option<ChannelHalf<R>>,
+        fun: impl Task<Output = R>,
+        priority: Priority,
+        meta: TaskMeta
+    ) -> Self
     where
         R: Sized + Send + 'static,
     {
         Self {
-            fun: Box::new(move || {
-                let value = catch_unwind(AssertUnwindSafe(move || fun.run()));
+            priority,
+            channel: channel.as_ref().map(ChannelHalf::cancel_handle),
+            meta,
+            queued_at: Instant::now(),
+            fun: Some(Box::new(move |on_panic: PanicFn<'_>| {
+                // A task which was aborted while waiting in the queue is dropped without running.
+                if matches!(&channel, Some(channel) if !channel.start()) {
+                    return Outcome::Cancelled;
+                }
+
+                let value = catch_unwind(AssertUnwindSafe(move || fun.run()))
+                    .map_err(|payload| {
+                        on_panic(&*payload);
+                        JoinError::panic(payload)
+                    });
+                let outcome = match value {
+                    Ok(_) => Outcome::Completed,
+                    Err(_) => Outcome::Panicked,
+                };
+
                 if let Some(channel) = channel {
                     channel.set(value)
                 }
+
+                outcome
+            })),
+        }
+    }
+
+    pub fn run(mut self, shared: &Shared, worker: Option<usize>) -> Outcome {
+        match self.fun.take() {
+            Some(fun) => fun(&|payload| {
+                shared.panicked(&TaskPanic::new(payload, &self.meta, worker))
             }),
+            None => Outcome::Cancelled,
+        }
+    }
+
+    /// Drops the task without running it, notifying the handle with the given error.
+    pub fn fail(self, error: JoinError) {
+        if let Some(channel) = &self.channel {
+            channel.fail(error);
         }
     }
 }
 
+impl Drop for SyncTask {
+    fn drop(&mut self) {
+        // The task is being dropped without running, which only happens when the pool shuts
+        // down, so let the handle know instead of leaving it waiting forever.
+        if let (Some(_), Some(channel)) = (&self.fun, &self.channel) {
+            channel.fail(JoinError::shutdown());
+        }
+    }
+}
+
+/// The future is waiting to be woken.
+const IDLE: u8 = 0;
+/// The task is waiting in the queue to be polled.
+const SCHEDULED: u8 = 1;
+/// The future is being polled by a worker.
+const RUNNING: u8 = 2;
+/// The future was woken while being polled, so it must be polled again.
+const NOTIFIED: u8 = 3;
+/// The future completed or was discarded.
+const COMPLETE: u8 = 4;
+
+/// A type erased future which sends its output through a channel.
+trait AsyncFun: Send {
+    fn poll(&mut self, cx: &mut Context<'_>, on_panic: PanicFn<'_>) -> Poll<Outcome>;
+
+    /// Drops the future without completing it, notifying the handle with the given error.
+    fn fail(&mut self, error: JoinError);
+}
+
+struct FutureFun<F>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    future: Pin<Box<F>>,
+    channel: Option<ChannelHalf<F::Output>>,
+    started: bool,
+    /// The pool of the task, used to tell why the future was dropped.
+    shared: Arc<Shared>,
+}
+
+impl<F> AsyncFun for FutureFun<F>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    fn poll(&mut self, cx: &mut Context<'_>, on_panic: PanicFn<'_>) -> Poll<Outcome> {
+        if !self.started {
+            self.started = true;
+
+            // A task which was aborted while waiting in the queue is dropped without running.
+            if matches!(&self.channel, Some(channel) if !channel.start()) {
+                self.channel = None;
+                return Poll::Ready(Outcome::Cancelled);
+            }
+        }
+
+        let poll = catch_unwind(AssertUnwindSafe(|| self.future.as_mut().poll(cx)));
+        let (value, outcome) = match poll {
+            Ok(Poll::Pending) => return Poll::Pending,
+            Ok(Poll::Ready(value)) => (Ok(value), Outcome::Completed),
+            Err(payload) => {
+                on_panic(&*payload);
+                (Err(JoinError::panic(payload)), Outcome::Panicked)
+            }
+        };
+
+        if let Some(channel) = self.channel.take() {
+            channel.set(value);
+        }
+
+        Poll::Ready(outcome)
+    }
+
+    fn fail(&mut self, error: JoinError) {
+        if let Some(channel) = self.channel.take() {
+            // A task which didn't start may have been aborted, which must not be overwritten.
+            match self.started {
+                true => channel.set(Err(error)),
+                false => {
+                    channel.fail(error);
+                }
+            }
+        }
+    }
+}
+
+impl<F> Drop for FutureFun<F>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    fn drop(&mut self) {
+        // The future is being dropped before completing, which happens when the pool shuts down
+        // or when nothing can wake it anymore, so let the handle know instead of leaving it
+        // waiting forever.
+        let error = match self.shared.is_closed() {
+            true => JoinError::shutdown(),
+            false => JoinError::abandoned(),
+        };
+        self.fail(error);
+    }
+}
+
+/// The data of an asynchronous task, shared between the task and its wakers.
+pub struct AsyncInner {
+    shared: Arc<Shared>,
+    future: Mutex<Option<Box<dyn AsyncFun>>>,
+    state: AtomicU8,
+    priority: Priority,
+    meta: TaskMeta,
+}
+
+impl Wake for AsyncInner {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref()
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        let mut state = self.state.load(Ordering::Acquire);
+
+        loop {
+            // Idle tasks are queued again, running ones are queued once they finish polling.
+            let next = match state {
+                IDLE => SCHEDULED,
+                RUNNING => NOTIFIED,
+                _ => return,
+            };
+
+            match self.state.compare_exchange(state, next, Ordering::AcqRel, Ordering::Acquire) {
+                Ok(_) if state == IDLE => {
+                    return AsyncTask::from_inner(Arc::clone(self)).schedule()
+                }
+                Ok(_) => return,
+                Err(actual) => state = actual,
+            }
+        }
+    }
+}
+
+/// A task which polls a future, being queued again every time the future is woken.
+pub struct AsyncTask {
+    inner: Arc<AsyncInner>,
+    queued_at: Instant,
+}
+
+impl AsyncTask {
+    pub fn new<F>(
+        shared: Arc<Shared>,
+        channel: Option<ChannelHalf<F::Output>>,
+        future: F,
+        priority: Priority,
+        meta: TaskMeta
+    ) -> Self
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        let fun = FutureFun {
+            future: Box::pin(future),
+            channel,
+            started: false,
+            shared: Arc::clone(&shared),
+        };
+
+        Self::from_inner(Arc::new(AsyncInner {
+            shared,
+            future: Mutex::new(Some(Box::new(fun))),
+            state: AtomicU8::new(SCHEDULED),
+            priority,
+            meta,
+        }))
+    }
+
+    fn from_inner(inner: Arc<AsyncInner>) -> Self {
+        Self {
+            inner,
+            queued_at: Instant::now(),
+        }
+    }
+
+    pub fn run(self, shared: &Shared, worker: Option<usize>) -> Outcome {
+        let inner = self.inner;
+        inner.state.store(RUNNING, Ordering::Release);
+
+        let waker = Waker::from(Arc::clone(&inner));
+        let mut cx = Context::from_waker(&waker);
+
+        {
+            let mut future = inner.future.lock();
+            let poll = match future.as_mut() {
+                Some(future) => future.poll(&mut cx, &|payload| {
+                    shared.panicked(&TaskPanic::new(payload, &inner.meta, worker))
+                }),
+                None => Poll::Ready(Outcome::Cancelled),
+            };
+
+            if let Poll::Ready(outcome) = poll {
+                *future = None;
+                inner.state.store(COMPLETE, Ordering::Release);
+                return outcome;
+            }
+        }
+
+        let idle = inner.state
+            .compare_exchange(RUNNING, IDLE, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok();
+
+        // The future was woken while being polled, so it must be queued again.
+        if !idle {
+            inner.state.store(SCHEDULED, Ordering::Release);
+            Self::from_inner(inner).schedule();
+        }
+
+        Outcome::Pending
+    }
+
+    fn schedule(self) {
+        let shared = Arc::clone(&self.inner.shared);
+
+        if let Err(task) = shared.schedule(TaskType::Async(self)) {
+            task.discard();
+        }
+    }
+
+    /// Drops the future without polling it again, notifying the handle with the given error.
+    pub fn fail(self, error: JoinError) {
+        self.inner.state.store(COMPLETE, Ordering::Release);
+        let future = self.inner.future.lock().take();
+
+        if let Some(mut future) = future {
+            future.fail(error);
+        }
+    }
+}
+
+/// A task which will be scheduled once its deadline is reached.
+pub struct DelayedTask {
+    shared: Arc<Shared>,
+    task: SyncTask,
+    at: Instant,
+}
+
+impl DelayedTask {
+    pub fn new(shared: Arc<Shared>, task: SyncTask, at: Instant) -> Self {
+        Self {
+            shared,
+            task,
+            at
+        }
+    }
+
+    pub fn schedule(self) {
+        // The pool may have shut down while the task waited in the timer, it's discarded then,
+        // unless it was aborted meanwhile.
+        if self.shared.is_closed() {
+            self.task.fail(JoinError::shutdown());
+            return;
+        }
+
+        // If the pool closes meanwhile the task is dropped, notifying its handle.
+        let _ = self.shared.schedule(TaskType::Sync(self.task));
+    }
+
+    pub fn deadline(&self) -> Instant {
+        self.at
+    }
+
+    pub fn can_run(&self) -> bool {
+        Instant::now() >= self.at
+    }
+}
+
 pub struct PeriodicTask {
     shared: Arc<Shared>,
-    fun: Box<dyn Fn() + Send + 'static>,
-    every: Duration,
-    next: Instant,
+    fun: Box<dyn Fn(PanicFn<'_>) -> Outcome + Send + 'static>,
+    state: Arc<PeriodicState>,
     times: Option<usize>,
+    priority: Priority,
+    meta: TaskMeta,
+    queued_at: Instant,
 }
 
 impl PeriodicTask {
-    pub fn new<F>(shared: Arc<Shared>, fun: F, every: Duration, times: Option<usize>) -> Self
+    pub fn new<F>(
+        shared: Arc<Shared>,
+        fun: F,
+        every: Duration,
+        times: Option<usize>,
+        priority: Priority,
+        meta: TaskMeta
+    ) -> Self
     where
         F: Fn() + Send + 'static
     {
-        let next = Instant::now() + every;
+        shared.counters.periodic_registered();
 
         Self {
             shared,
-            fun: Box::new(move || {
-                let _ = catch_unwind(AssertUnwindSafe(|| (fun)()));
+            fun: Box::new(move |on_panic: PanicFn<'_>| {
+                match catch_unwind(AssertUnwindSafe(|| (fun)())) {
+                    Ok(()) => Outcome::Completed,
+                    Err(payload) => {
+                        on_panic(&*payload);
+                        Outcome::Panicked
+                    }
+                }
             }),
-            every,
-            next,
-            times
+            state: PeriodicState::new(every),
+            times,
+            priority,
+            meta,
+            queued_at: Instant::now(),
         }
     }
 
-    pub fn run(mut self) {
-        (self.fun)();
-        self.times.as_mut().map(|t| *t = *t-1);
+    /// Returns the state of the task, shared with its [handle](crate::PeriodicHandle).
+    pub fn state(&self) -> Arc<PeriodicState> {
+        Arc::clone(&self.state)
+    }
+
+    pub fn run(mut self, shared: &Shared, worker: Option<usize>) -> Outcome {
+        // The task may have been cancelled while waiting in the queue.
+        if self.state.is_cancelled() {
+            return Outcome::Cancelled;
+        }
+
+        let outcome = (self.fun)(&|payload| {
+            shared.panicked(&TaskPanic::new(payload, &self.meta, worker))
+        });
+        if let Some(times) = self.times.as_mut() {
+            *times = times.saturating_sub(1);
+        }
+
+        let again = self.times.map(|t| t >= 1).unwrap_or(true);
+        self.state.ran(again);
 
-        if self.times.is_none() || self.times.as_ref().map(|t| *t >= 1).unwrap() {
-            self.next = Instant::now() + self.every;
+        // A task cancelled while running is dropped instead of going back to the timer.
+        if again && !self.state.is_cancelled() {
             self.reschedule();
         }
+
+        outcome
     }
 
     pub fn schedule(self) {
-        if self.shared.should_exit() {
-            drop(self);
-        } else {
-            // SAFETY: As the task is holding the Arc we ensure the pointer is valid, also as we're
-            // not modifying its contents, we avoid any possible data races. This way of scheduling
-            // the task is just a workaround to avoid cloning the Arc every time.
-            unsafe { (&*Arc::as_ptr(&self.shared)).schedule(TaskType::Periodic(self)); }
+        // SAFETY: As the task is holding the Arc we ensure the pointer is valid, also as we're
+        // not modifying its contents, we avoid any possible data races. This way of scheduling
+        // the task is just a workaround to avoid cloning the Arc every time. If the pool is
+        // closed the task is given back and dropped once the call returns.
+        let shared = unsafe { &*Arc::as_ptr(&self.shared) };
+
+        if shared.schedule(TaskType::Periodic(self)).is_ok() {
+            shared.counters.spawned();
         }
     }
 
+    /// Skips the current run, scheduling the next one.
+    pub fn skip(self) {
+        self.state.skip();
+        self.reschedule();
+    }
+
+    /// Hands the task to the timer of its pool, it's dropped if the timer stopped.
     pub fn reschedule(self) {
-        crate::context::get_timer()
-            .schedule(self);
+        let shared = Arc::clone(&self.shared);
+
+        if let Some(timer) = shared.timer() {
+            timer.schedule(self);
+        }
     }
 
     pub fn can_run(&self) -> bool {
-        Instant::now() > self.next
+        self.state.can_run()
+    }
+
+    /// The next time the task should run, [None](None) if it's paused.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.state.deadline()
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.state.is_cancelled()
+    }
+}
+
+impl Drop for PeriodicTask {
+    fn drop(&mut self) {
+        // Once dropped the task won't run anymore.
+        self.state.finish();
+        self.shared.counters.periodic_finished();
+    }
+}
+
+/// The type erased function of a [join](crate::Handle::join) which workers can steal.
+pub trait Steal: Send + Sync {
+    /// Runs the function, returning whether it panicked.
+    fn steal(&self) -> bool;
+}
+
+/// A pointer to the function of a join, which lives on the stack of the joining thread.
+struct JobRef(*const (dyn Steal + 'static));
+
+// SAFETY: The function is `Send + Sync`, and it is kept alive by the joining thread for as long
+// as a reference to it is out, see `JoinSlot::new`.
+unsafe impl Send for JobRef {}
+
+/// The state of a join shared between the joining thread and its queued task. It only holds
+/// `'static` data, so the task can safely outlive the join when it is left in the queue.
+pub struct JoinSlot {
+    /// The function, taken by the task once it leaves the queues or by the joining thread,
+    /// whichever comes first.
+    job: Mutex<Option<JobRef>>,
+    /// Whether the place of the task in the queue was given back.
+    dequeued: AtomicBool,
+    /// Whether the task finished running the function.
+    done: AtomicBool,
+    /// The waker of the joining thread, waiting for the task to finish.
+    waker: Mutex<Option<Waker>>,
+}
+
+impl JoinSlot {
+    /// Creates the slot of the given function.
+    ///
+    /// # Safety
+    ///
+    /// The function must be kept alive until it is [reclaimed](Self::reclaim) or, if that
+    /// fails, until [poll_done](Self::poll_done) is ready.
+    pub unsafe fn new(job: &(dyn Steal + '_)) -> Arc<Self> {
+        let job: *const (dyn Steal + '_) = job;
+
+        Arc::new(Self {
+            // SAFETY: Only the lifetime is erased, the caller keeps the function alive while
+            // the pointer can be used.
+            job: Mutex::new(Some(JobRef(unsafe {
+                std::mem::transmute::<*const (dyn Steal + '_), *const (dyn Steal + 'static)>(job)
+            }))),
+            dequeued: AtomicBool::new(false),
+            done: AtomicBool::new(false),
+            waker: Mutex::new(None),
+        })
+    }
+
+    /// Registers the task leaving the queues, returning whether it still held its place in the
+    /// queue, which must be given back then.
+    pub fn dequeue(&self) -> bool {
+        !self.dequeued.swap(true, Ordering::AcqRel)
+    }
+
+    /// Takes the function back for the joining thread, returning whether the task didn't take
+    /// it first. The place of the task in the queue is given back right away, the task is
+    /// dropped without running once it leaves the queues.
+    pub fn reclaim(&self, shared: &Shared) -> bool {
+        if self.job.lock().take().is_none() {
+            return false;
+        }
+
+        if self.dequeue() {
+            shared.released();
+        }
+
+        true
+    }
+
+    /// Polls for the task to finish running the function, ready with false if the task was
+    /// dropped without running it, so the joining thread must run it instead.
+    pub fn poll_done(&self, cx: &mut Context<'_>) -> Poll<bool> {
+        let mut waker = self.waker.lock();
+
+        if self.done.load(Ordering::Acquire) {
+            Poll::Ready(true)
+        } else if self.job.lock().take().is_some() {
+            Poll::Ready(false)
+        } else {
+            *waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+
+    /// Wakes the joining thread, after the function ran or was given back.
+    fn notify(&self, update: impl FnOnce()) {
+        let waker = {
+            let mut waker = self.waker.lock();
+            update();
+            waker.take()
+        };
+
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+/// The queued half of a [join](crate::Handle::join), which idle workers can steal. The joining
+/// thread takes the function back if the task didn't leave the queues by the time it needs it,
+/// the task is then dropped without running nor being counted once it does.
+pub struct JoinTask {
+    slot: Arc<JoinSlot>,
+    /// The function, once it was claimed.
+    job: Option<JobRef>,
+    meta: TaskMeta,
+    queued_at: Instant,
+}
+
+impl JoinTask {
+    pub fn new(slot: Arc<JoinSlot>, meta: TaskMeta) -> Self {
+        Self {
+            slot,
+            job: None,
+            meta,
+            queued_at: Instant::now(),
+        }
+    }
+
+    pub fn slot(&self) -> &JoinSlot {
+        &self.slot
+    }
+
+    /// Takes the function once the task leaves the queues, returning false if the joining
+    /// thread already took it back.
+    pub fn claim(&mut self) -> bool {
+        self.job = self.slot.job.lock().take();
+        self.job.is_some()
+    }
+
+    /// Runs the claimed function, panics are given to the joining thread instead of the panic
+    /// handler of the pool. The task is only counted as spawned once it runs.
+    pub fn run(mut self, shared: &Shared) -> Outcome {
+        let job = self.job.take().expect("Join task ran without claiming its function");
+        shared.counters.spawned();
+
+        // SAFETY: The joining thread waits for the task to be done with the function.
+        let panicked = unsafe { &*job.0 }.steal();
+        self.slot.notify(|| self.slot.done.store(true, Ordering::Release));
+
+        match panicked {
+            false => Outcome::Completed,
+            true => Outcome::Panicked,
+        }
+    }
+}
+
+impl Drop for JoinTask {
+    fn drop(&mut self) {
+        // A claimed function which didn't run is given back to the joining thread.
+        if let Some(job) = self.job.take() {
+            self.slot.notify(|| *self.slot.job.lock() = Some(job));
+        }
     }
 }
@@ -0,0 +1,285 @@
+use crate::{
+    builder::RejectionPolicy,
+    channel::ChannelHalf,
+    error::SpawnError,
+    handle::Handle,
+    join::{JoinError, JoinHandle},
+    periodic::PeriodicHandle,
+    task::{AsyncTask, DelayedTask, PeriodicTask, Priority, SyncTask, Task, TaskMeta, TaskType},
+};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// A builder which allows to configure a task before spawning it, this is returned by
+/// [task_builder](Handle::task_builder).
+///
+/// The spawn methods of [Handle](Handle) are equivalent to the ones of a builder with the
+/// default configuration.
+#[derive(Clone, Copy)]
+pub struct TaskBuilder<'a> {
+    handle: &'a Handle,
+    priority: Priority,
+    name: Option<&'a str>,
+}
+
+impl<'a> TaskBuilder<'a> {
+    pub(crate) fn new(handle: &'a Handle) -> Self {
+        Self {
+            handle,
+            priority: Priority::default(),
+            name: None,
+        }
+    }
+
+    /// Sets the [priority](Priority) of the task, by default [Normal](Priority::Normal).
+    pub fn priority(mut self, priority: Priority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Sets the name of the task, given to the [panic handler](crate::TaskPanic::task_name)
+    /// and to the [task hooks](crate::TaskInfo::name) of the pool.
+    pub fn name(mut self, name: &'a str) -> Self {
+        self.name = Some(name);
+        self
+    }
+
+    /// Spawns the task into the thread pool, see [Handle::spawn](Handle::spawn).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the task can't be spawned, see [try_spawn](Self::try_spawn) for a non
+    /// panicking alternative.
+    #[track_caller]
+    pub fn spawn<T, R>(self, task: T) -> JoinHandle<R>
+    where
+        T: Task<Output = R>,
+        R: Sized + Send + 'static,
+    {
+        match self.try_spawn(task) {
+            Ok(handle) => handle,
+            Err(SpawnError::QueueFull(task)) if self.is_drop_new() => {
+                let (rx, tx) = ChannelHalf::<R>::new_pair();
+                SyncTask::new(Some(tx), task, self.priority, TaskMeta::new(self.name))
+                    .fail(JoinError::rejected());
+                JoinHandle::new(rx)
+            }
+            Err(error) => panic!("Cannot spawn a task, {}.", error),
+        }
+    }
+
+    /// Tries to spawn the task into the thread pool, see [Handle::try_spawn](Handle::try_spawn).
+    #[track_caller]
+    pub fn try_spawn<T, R>(self, task: T) -> Result<JoinHandle<R>, SpawnError<T>>
+    where
+        T: Task<Output = R>,
+        R: Sized + Send + 'static,
+    {
+        let (rx, tx) = ChannelHalf::<R>::new_pair();
+        let (priority, meta) = (self.priority, TaskMeta::new(self.name));
+        self.handle.spawn_inner(task, move |task| {
+            TaskType::Sync(SyncTask::new(Some(tx), task, priority, meta))
+        })?;
+        Ok(JoinHandle::new(rx))
+    }
+
+    /// Spawns the task into the thread pool without returning a handle, see
+    /// [Handle::spawn_detached](Handle::spawn_detached).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the task can't be spawned, see [try_spawn_detached](Self::try_spawn_detached)
+    /// for a non panicking alternative.
+    #[track_caller]
+    pub fn spawn_detached<T, R>(self, task: T)
+    where
+        T: Task<Output = R>,
+        R: Sized + Send + 'static,
+    {
+        match self.try_spawn_detached(task) {
+            Ok(()) => (),
+            Err(SpawnError::QueueFull(_)) if self.is_drop_new() => (),
+            Err(error) => panic!("Cannot spawn a task, {}.", error),
+        }
+    }
+
+    /// Tries to spawn the task into the thread pool without returning a handle, see
+    /// [Handle::try_spawn_detached](Handle::try_spawn_detached).
+    #[track_caller]
+    pub fn try_spawn_detached<T, R>(self, task: T) -> Result<(), SpawnError<T>>
+    where
+        T: Task<Output = R>,
+        R: Sized + Send + 'static,
+    {
+        let (priority, meta) = (self.priority, TaskMeta::new(self.name));
+        self.handle.spawn_inner(task, move |task| {
+            TaskType::Sync(SyncTask::new(None, task, priority, meta))
+        })
+    }
+
+    /// Spawns the future into the thread pool, see [Handle::spawn_async](Handle::spawn_async).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the task can't be spawned, see [try_spawn_async](Self::try_spawn_async) for a
+    /// non panicking alternative.
+    #[track_caller]
+    pub fn spawn_async<F>(self, future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        match self.try_spawn_async(future) {
+            Ok(handle) => handle,
+            Err(SpawnError::QueueFull(_)) if self.is_drop_new() => {
+                let (rx, tx) = ChannelHalf::new_pair();
+                tx.set(Err(JoinError::rejected()));
+                JoinHandle::new(rx)
+            }
+            Err(error) => panic!("Cannot spawn a task, {}.", error),
+        }
+    }
+
+    /// Tries to spawn the future into the thread pool, see
+    /// [Handle::try_spawn_async](Handle::try_spawn_async).
+    #[track_caller]
+    pub fn try_spawn_async<F>(self, future: F) -> Result<JoinHandle<F::Output>, SpawnError<F>>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        let (rx, tx) = ChannelHalf::new_pair();
+        let (shared, priority) = (Arc::clone(&self.handle.shared), self.priority);
+        let meta = TaskMeta::new(self.name);
+        self.handle.spawn_inner(future, move |future| {
+            TaskType::Async(AsyncTask::new(shared, Some(tx), future, priority, meta))
+        })?;
+        Ok(JoinHandle::new(rx))
+    }
+
+    /// Spawns the task into the thread pool once the given [delay](Duration) elapses, see
+    /// [Handle::spawn_after](Handle::spawn_after).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the pool was shut down or its [timer](crate::ThreadPoolBuilder::timer) is
+    /// disabled, see [try_spawn_after](Self::try_spawn_after) for a non panicking alternative.
+    #[track_caller]
+    pub fn spawn_after<T, R>(self, delay: Duration, task: T) -> JoinHandle<R>
+    where
+        T: Task<Output = R>,
+        R: Sized + Send + 'static,
+    {
+        self.spawn_at(Instant::now() + delay, task)
+    }
+
+    /// Tries to spawn the task into the thread pool once the given [delay](Duration) elapses,
+    /// see [Handle::try_spawn_after](Handle::try_spawn_after).
+    #[track_caller]
+    pub fn try_spawn_after<T, R>(
+        self,
+        delay: Duration,
+        task: T
+    ) -> Result<JoinHandle<R>, SpawnError<T>>
+    where
+        T: Task<Output = R>,
+        R: Sized + Send + 'static,
+    {
+        self.try_spawn_at(Instant::now() + delay, task)
+    }
+
+    /// Spawns the task into the thread pool once the given [instant](Instant) is reached, see
+    /// [Handle::spawn_at](Handle::spawn_at).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the pool was shut down or its [timer](crate::ThreadPoolBuilder::timer) is
+    /// disabled, see [try_spawn_at](Self::try_spawn_at) for a non panicking alternative.
+    #[track_caller]
+    pub fn spawn_at<T, R>(self, at: Instant, task: T) -> JoinHandle<R>
+    where
+        T: Task<Output = R>,
+        R: Sized + Send + 'static,
+    {
+        self.try_spawn_at(at, task)
+            .unwrap_or_else(|error| panic!("Cannot spawn a task, {}.", error))
+    }
+
+    /// Tries to spawn the task into the thread pool once the given [instant](Instant) is
+    /// reached, see [Handle::try_spawn_at](Handle::try_spawn_at).
+    #[track_caller]
+    pub fn try_spawn_at<T, R>(self, at: Instant, task: T) -> Result<JoinHandle<R>, SpawnError<T>>
+    where
+        T: Task<Output = R>,
+        R: Sized + Send + 'static,
+    {
+        let shared = &self.handle.shared;
+
+        if shared.is_closed() {
+            return Err(SpawnError::Shutdown(task));
+        }
+
+        let timer = match shared.timer() {
+            Some(timer) => timer,
+            None => return Err(SpawnError::NoTimer(task)),
+        };
+        let (rx, tx) = ChannelHalf::<R>::new_pair();
+        let task = SyncTask::new(Some(tx), task, self.priority, TaskMeta::new(self.name));
+        shared.counters.spawned();
+
+        timer.schedule_delayed(DelayedTask::new(Arc::clone(shared), task, at));
+
+        Ok(JoinHandle::new(rx))
+    }
+
+    /// Creates a new periodic task, every run of the task is queued with the priority of the
+    /// builder, see [Handle::periodic](Handle::periodic).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the pool was shut down or its [timer](crate::ThreadPoolBuilder::timer) is
+    /// disabled, see [try_periodic](Self::try_periodic) for a non panicking alternative.
+    #[track_caller]
+    pub fn periodic<F>(self, fun: F, every: Duration, times: Option<usize>) -> PeriodicHandle
+    where
+        F: Fn() + Send + 'static
+    {
+        self.try_periodic(fun, every, times)
+            .unwrap_or_else(|error| panic!("Cannot spawn a task, {}.", error))
+    }
+
+    /// Tries to create a new periodic task, see [Handle::try_periodic](Handle::try_periodic).
+    #[track_caller]
+    pub fn try_periodic<F>(
+        self,
+        fun: F,
+        every: Duration,
+        times: Option<usize>
+    ) -> Result<PeriodicHandle, SpawnError<F>>
+    where
+        F: Fn() + Send + 'static
+    {
+        let shared = &self.handle.shared;
+
+        if shared.is_closed() {
+            return Err(SpawnError::Shutdown(fun));
+        }
+
+        let timer = match shared.timer() {
+            Some(timer) => timer,
+            None => return Err(SpawnError::NoTimer(fun)),
+        };
+        let meta = TaskMeta::new(self.name);
+        let task = PeriodicTask::new(Arc::clone(shared), fun, every, times, self.priority, meta);
+        let handle = PeriodicHandle::new(task.state(), Some(timer.waker()));
+
+        timer.schedule(task);
+
+        Ok(handle)
+    }
+
+    fn is_drop_new(&self) -> bool {
+        self.handle.shared.policy == RejectionPolicy::DropNew
+    }
+}
@@ -73,3 +73,1180 @@ async fn wait_async() -> std::io::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn spawn_many() -> std::io::Result<()> {
+    use std::sync::{atomic::{AtomicUsize, Ordering}, Arc};
+
+    let pool = ThreadPoolBuilder::new().thread_number(4).build()?;
+    let counter = Arc::new(AtomicUsize::new(0));
+
+    let handles = (0..100)
+        .map(|_| {
+            let handle = pool.handle();
+            let counter = Arc::clone(&counter);
+            // Tasks spawned from a worker go into its local queue and can be stolen by siblings.
+            pool.spawn(move || {
+                for _ in 0..10 {
+                    let counter = Arc::clone(&counter);
+                    handle.spawn_detached(move || counter.fetch_add(1, Ordering::Relaxed));
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+
+    for handle in handles {
+        handle.wait().unwrap();
+    }
+
+    while counter.load(Ordering::Relaxed) < 1000 {
+        std::thread::yield_now();
+    }
+
+    pool.shutdown();
+    Ok(())
+}
+
+#[test]
+fn abort_queued() -> std::io::Result<()> {
+    use std::sync::{atomic::{AtomicBool, Ordering}, Arc, Barrier};
+
+    let pool = ThreadPoolBuilder::new().thread_number(1).build()?;
+    let barrier = Arc::new(Barrier::new(2));
+    let ran = Arc::new(AtomicBool::new(false));
+
+    // Keep the only worker busy so the next task stays in the queue.
+    let blocker = {
+        let barrier = Arc::clone(&barrier);
+        pool.spawn(move || { barrier.wait(); })
+    };
+    let handle = {
+        let ran = Arc::clone(&ran);
+        pool.spawn(move || ran.store(true, Ordering::Relaxed))
+    };
+
+    assert!(handle.abort_handle().abort());
+    assert!(!handle.abort());
+    barrier.wait();
+    blocker.wait().unwrap();
+
+    assert!(handle.wait().unwrap_err().is_cancelled());
+    assert!(!ran.load(Ordering::Relaxed));
+
+    pool.shutdown();
+    Ok(())
+}
+
+#[test]
+fn periodic_handle() -> std::io::Result<()> {
+    use std::time::Duration;
+
+    let pool = ThreadPoolBuilder::new().build()?;
+    let handle = pool.periodic(|| {}, Duration::from_millis(10), None);
+    assert!(handle.next_run().is_some());
+
+    while handle.run_count() < 2 {
+        std::thread::sleep(Duration::from_millis(10));
+    }
+
+    handle.pause();
+    assert!(handle.is_paused() && handle.next_run().is_none());
+    handle.resume();
+    assert!(handle.next_run().is_some());
+
+    handle.cancel();
+    assert!(handle.is_cancelled() && handle.next_run().is_none());
+    // Let a run which may have already started finish.
+    std::thread::sleep(Duration::from_millis(50));
+    let count = handle.run_count();
+    std::thread::sleep(Duration::from_millis(300));
+    assert_eq!(handle.run_count(), count);
+
+    let limited = pool.periodic(|| {}, Duration::from_millis(10), Some(1));
+    while limited.run_count() < 1 {
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    assert!(limited.next_run().is_none());
+
+    // A task cancelled while running doesn't get a next run once the run finishes.
+    let state = crate::periodic::PeriodicState::new(Duration::from_millis(10));
+    let running = PeriodicHandle::new(std::sync::Arc::clone(&state), None);
+    running.cancel();
+    state.ran(true);
+    assert!(running.next_run().is_none());
+
+    pool.shutdown();
+    Ok(())
+}
+
+#[test]
+fn periodic_resume() -> std::io::Result<()> {
+    use std::time::{Duration, Instant};
+
+    let pool = ThreadPoolBuilder::new().thread_number(1).build()?;
+    let handle = pool.periodic(|| {}, Duration::from_millis(10), None);
+    handle.pause();
+
+    // The timer sleeps for longer the longer nothing is due, resuming the task wakes it up.
+    std::thread::sleep(Duration::from_secs(2));
+    let count = handle.run_count();
+    handle.resume();
+    let resumed = Instant::now();
+    while handle.run_count() == count && resumed.elapsed() < Duration::from_secs(5) {
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    assert!(resumed.elapsed() < Duration::from_millis(200));
+
+    pool.shutdown();
+    Ok(())
+}
+
+#[test]
+fn spawn_after() -> std::io::Result<()> {
+    use std::time::{Duration, Instant};
+
+    let pool = ThreadPoolBuilder::new().build()?;
+    let start = Instant::now();
+
+    let after = pool.spawn_after(Duration::from_millis(200), Instant::now);
+    let at = pool.spawn_at(start + Duration::from_millis(100), Instant::now);
+
+    assert!(at.wait().unwrap() >= start + Duration::from_millis(100));
+    assert!(after.wait().unwrap() >= start + Duration::from_millis(200));
+    assert!(start.elapsed() < Duration::from_secs(1));
+
+    pool.shutdown();
+    Ok(())
+}
+
+#[test]
+fn join_error() -> std::io::Result<()> {
+    let pool = ThreadPoolBuilder::new().build()?;
+
+    let error = pool.spawn(|| panic!("task failed")).wait().unwrap_err();
+    assert!(error.is_panic() && !error.is_cancelled());
+    assert_eq!(error.panic_message(), Some("task failed"));
+    assert_eq!(error.to_string(), "task panicked with message \"task failed\"");
+
+    let error = pool.spawn(|| std::panic::panic_any(1)).wait().unwrap_err();
+    assert_eq!(error.panic_message(), None);
+    assert_eq!(error.into_panic().downcast_ref::<i32>(), Some(&1));
+
+    pool.shutdown();
+    Ok(())
+}
+
+#[test]
+fn wait_timeout() -> std::io::Result<()> {
+    use std::time::Duration;
+
+    let pool = ThreadPoolBuilder::new().build()?;
+    let handle = pool.spawn(|| {
+        std::thread::sleep(Duration::from_millis(200));
+        "done"
+    });
+
+    let handle = handle.try_wait().unwrap_err();
+    assert!(!handle.is_finished());
+    let handle = handle.wait_timeout(Duration::from_millis(10)).unwrap_err();
+    assert_eq!(handle.wait_timeout(Duration::from_secs(5)).unwrap().unwrap(), "done");
+
+    let handle = pool.spawn(|| "done");
+    while !handle.is_finished() {
+        std::thread::yield_now();
+    }
+    assert_eq!(handle.try_wait().unwrap().unwrap(), "done");
+
+    pool.shutdown();
+    Ok(())
+}
+
+#[test]
+fn shutdown_graceful() -> std::io::Result<()> {
+    use std::time::Duration;
+
+    let pool = ThreadPoolBuilder::new().thread_number(1).build()?;
+    let handles = (0..5)
+        .map(|i| pool.spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            i
+        }))
+        .collect::<Vec<_>>();
+    let panicked = pool.spawn(|| panic!("graceful shutdown panic"));
+
+    // Panicked tasks don't count as completed.
+    let report = pool.shutdown_graceful(Duration::from_secs(5));
+    assert_eq!(report, ShutdownReport { completed: 5, discarded: 0 });
+    for (i, handle) in handles.into_iter().enumerate() {
+        assert_eq!(handle.wait().unwrap(), i);
+    }
+    assert!(panicked.wait().unwrap_err().is_panic());
+
+    let pool = ThreadPoolBuilder::new().thread_number(1).build()?;
+    let barrier = std::sync::Arc::new(std::sync::Barrier::new(2));
+    let blocker = {
+        let barrier = std::sync::Arc::clone(&barrier);
+        pool.spawn(move || {
+            barrier.wait();
+            std::thread::sleep(Duration::from_millis(200));
+        })
+    };
+    let handles = (0..3).map(|_| pool.spawn(|| ())).collect::<Vec<_>>();
+    let delayed = pool.spawn_after(Duration::from_secs(60), || ());
+    barrier.wait();
+
+    // Delayed tasks which weren't due yet count as discarded.
+    let report = pool.shutdown_graceful(Duration::from_millis(50));
+    assert_eq!(report, ShutdownReport { completed: 1, discarded: 4 });
+    blocker.wait().unwrap();
+    for handle in handles {
+        assert!(handle.wait().unwrap_err().is_shutdown());
+    }
+    assert!(delayed.wait().unwrap_err().is_shutdown());
+
+    Ok(())
+}
+
+#[test]
+fn try_spawn_shutdown() -> std::io::Result<()> {
+    let pool = ThreadPoolBuilder::new().build()?;
+    let handle = pool.handle();
+    assert_eq!(handle.try_spawn(|| 1).unwrap().wait().unwrap(), 1);
+    pool.shutdown();
+
+    let error = handle.try_spawn(|| 2).unwrap_err();
+    assert!(error.is_shutdown());
+    assert_eq!(error.into_inner()(), 2);
+    assert!(handle.try_spawn_detached(|| ()).unwrap_err().is_shutdown());
+    assert!(handle
+        .try_periodic(|| (), std::time::Duration::from_secs(1), None)
+        .unwrap_err()
+        .is_shutdown());
+    let error = handle.try_spawn_after(std::time::Duration::from_secs(1), || 3).unwrap_err();
+    assert!(error.is_shutdown());
+    assert_eq!(error.into_inner()(), 3);
+
+    Ok(())
+}
+
+#[test]
+fn queue_capacity() -> std::io::Result<()> {
+    use std::sync::{Arc, Barrier};
+
+    // Builds a single threaded pool with a blocked worker and a full queue.
+    let build = |policy| -> std::io::Result<_> {
+        let pool = ThreadPoolBuilder::new()
+            .thread_number(1)
+            .queue_capacity(2)
+            .rejection_policy(policy)
+            .build()?;
+        let barrier = Arc::new(Barrier::new(2));
+        let blocker = Arc::clone(&barrier);
+        pool.spawn_detached(move || { blocker.wait(); blocker.wait(); });
+        barrier.wait();
+        let queued = (0..2).map(|i| pool.spawn(move || i)).collect::<Vec<_>>();
+        Ok((pool, barrier, queued))
+    };
+
+    let (pool, barrier, queued) = build(RejectionPolicy::Error)?;
+    assert!(pool.try_spawn(|| 2).unwrap_err().is_queue_full());
+    barrier.wait();
+    assert_eq!(queued.into_iter().map(|h| h.wait().unwrap()).sum::<i32>(), 1);
+    pool.shutdown();
+
+    let (pool, barrier, queued) = build(RejectionPolicy::CallerRuns)?;
+    let thread = std::thread::current().id();
+    assert_eq!(pool.spawn(move || std::thread::current().id()).wait().unwrap(), thread);
+    barrier.wait();
+    drop(queued);
+    pool.shutdown();
+
+    let (pool, barrier, mut queued) = build(RejectionPolicy::DropOldest)?;
+    let newest = pool.spawn(|| 2);
+    barrier.wait();
+    assert!(queued.remove(0).wait().unwrap_err().is_rejected());
+    assert_eq!(queued.remove(0).wait().unwrap(), 1);
+    assert_eq!(newest.wait().unwrap(), 2);
+    pool.shutdown();
+
+    // An aborted task dropped to make room for newer ones stays cancelled.
+    let (pool, barrier, _queued) = build(RejectionPolicy::DropOldest)?;
+    let aborted = pool.spawn_async(async { 3 });
+    assert!(aborted.abort());
+    pool.spawn_detached(|| ());
+    pool.spawn_detached(|| ());
+    barrier.wait();
+    assert!(aborted.wait().unwrap_err().is_cancelled());
+    pool.shutdown();
+
+    let (pool, barrier, queued) = build(RejectionPolicy::DropNew)?;
+    assert!(pool.spawn(|| 2).wait().unwrap_err().is_rejected());
+    barrier.wait();
+    drop(queued);
+    pool.shutdown();
+
+    let (pool, barrier, queued) = build(RejectionPolicy::Block)?;
+    let spawner = {
+        let handle = pool.handle();
+        std::thread::spawn(move || handle.spawn(|| 2).wait().unwrap())
+    };
+    std::thread::sleep(std::time::Duration::from_millis(50));
+    assert!(!spawner.is_finished());
+    barrier.wait();
+    assert_eq!(spawner.join().unwrap(), 2);
+    drop(queued);
+    pool.shutdown();
+
+    Ok(())
+}
+
+#[test]
+fn priorities() -> std::io::Result<()> {
+    use std::sync::{Arc, Barrier};
+    use parking_lot::Mutex;
+
+    // Keeps the only worker of the pool busy until the returned barrier is waited.
+    let pool = ThreadPoolBuilder::new().thread_number(1).build()?;
+    let block = || {
+        let barrier = Arc::new(Barrier::new(2));
+        let blocker = Arc::clone(&barrier);
+        pool.spawn_detached(move || { blocker.wait(); blocker.wait(); });
+        barrier.wait();
+        barrier
+    };
+
+    let order = Arc::new(Mutex::new(Vec::new()));
+    let push = |priority| {
+        let order = Arc::clone(&order);
+        pool.task_builder()
+            .priority(priority)
+            .spawn(move || order.lock().push(priority))
+    };
+
+    // Higher priorities run first.
+    let barrier = block();
+    let handles = [Priority::Low, Priority::Normal, Priority::High].map(push);
+    barrier.wait();
+    handles.into_iter().for_each(|handle| handle.wait().unwrap());
+    assert_eq!(*order.lock(), [Priority::High, Priority::Normal, Priority::Low]);
+
+    // Low priority tasks don't starve behind a stream of high priority ones.
+    order.lock().clear();
+    let barrier = block();
+    let handles = std::iter::once(Priority::Low)
+        .chain((0..64).map(|_| Priority::High))
+        .map(push)
+        .collect::<Vec<_>>();
+    barrier.wait();
+    handles.into_iter().for_each(|handle| handle.wait().unwrap());
+    let low = order.lock().iter().position(|&p| p == Priority::Low).unwrap();
+    assert!(low < 64);
+
+    pool.shutdown();
+    Ok(())
+}
+
+#[test]
+fn spawn_async() -> std::io::Result<()> {
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+
+    // A future which wakes itself the given number of times before completing.
+    struct Yield(usize);
+
+    impl Future for Yield {
+        type Output = ();
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+            if self.0 == 0 {
+                return Poll::Ready(());
+            }
+
+            self.0 -= 1;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+
+    let pool = ThreadPoolBuilder::new().thread_number(2).build()?;
+    let handle = pool.handle();
+
+    let value = pool.spawn_async(async move {
+        Yield(10).await;
+        let value = handle.spawn(|| 20).await.unwrap();
+        Yield(10).await;
+        value + 1
+    });
+    assert_eq!(value.wait().unwrap(), 21);
+
+    let error = pool.spawn_async(async { panic!("async panic") }).wait().unwrap_err();
+    assert_eq!(error.panic_message(), Some("async panic"));
+
+    // Futures which can't be woken anymore are abandoned while the pool keeps running.
+    let pending = pool.spawn_async(std::future::pending::<()>());
+    let error = pending.wait().unwrap_err();
+    assert!(error.is_abandoned() && !error.is_shutdown());
+    pool.shutdown();
+
+    Ok(())
+}
+
+#[test]
+fn block_on() -> std::io::Result<()> {
+    let pool = ThreadPoolBuilder::new().thread_number(1).build()?;
+
+    let value = pool.block_on(pool.spawn(|| 1)).unwrap();
+    assert_eq!(value, 1);
+
+    // The only worker of the pool waits for a task queued after it, which it runs itself.
+    let handle = pool.handle();
+    let value = pool.spawn(move || {
+        let inner = handle.spawn(|| 2);
+        handle.block_on(async { inner.await.unwrap() + 1 })
+    });
+    assert_eq!(value.wait().unwrap(), 3);
+
+    pool.shutdown();
+    Ok(())
+}
+
+#[test]
+fn scope() -> std::io::Result<()> {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let pool = ThreadPoolBuilder::new().thread_number(2).build()?;
+    let data = (0..100).collect::<Vec<usize>>();
+    let sum = AtomicUsize::new(0);
+
+    let chunks = pool.scope(|s| {
+        for chunk in data.chunks(10) {
+            s.spawn(|| {
+                sum.fetch_add(chunk.iter().sum(), Ordering::Relaxed);
+            });
+        }
+
+        data.chunks(10).count()
+    });
+    assert_eq!(chunks, 10);
+    assert_eq!(sum.into_inner(), 4950);
+
+    let finished = AtomicUsize::new(0);
+    let panic = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        pool.scope(|s| {
+            s.spawn(|| panic!("scoped panic"));
+            s.spawn(|| {
+                std::thread::sleep(std::time::Duration::from_millis(50));
+                finished.fetch_add(1, Ordering::Relaxed);
+            });
+        })
+    }));
+    assert_eq!(panic.unwrap_err().downcast_ref::<&str>(), Some(&"scoped panic"));
+    assert_eq!(finished.into_inner(), 1);
+    pool.shutdown();
+
+    // A task dropped because the queue is full is reported as rejected, not as a shutdown.
+    let pool = ThreadPoolBuilder::new()
+        .thread_number(1)
+        .queue_capacity(1)
+        .rejection_policy(RejectionPolicy::DropNew)
+        .build()?;
+    let (started, release) = (std::sync::Barrier::new(2), std::sync::Barrier::new(2));
+    let panic = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        pool.scope(|s| {
+            s.spawn(|| { started.wait(); release.wait(); });
+            started.wait();
+            s.spawn(|| ());
+            s.spawn(|| ());
+            release.wait();
+        })
+    }));
+    let message = panic.unwrap_err().downcast_ref::<&str>().copied();
+    assert_eq!(message, Some("Scoped task discarded, rejected by the full task queue."));
+
+    pool.shutdown();
+    Ok(())
+}
+
+#[test]
+fn fork_join() -> std::io::Result<()> {
+    fn sum(handle: &Handle, values: &[u64]) -> u64 {
+        if values.len() <= 4 {
+            return values.iter().sum();
+        }
+
+        let (left, right) = values.split_at(values.len() / 2);
+        let (left, right) = handle.join(|| sum(handle, left), || sum(handle, right));
+        left + right
+    }
+
+    // Recursive joins from inside a small pool don't deadlock it.
+    let pool = ThreadPoolBuilder::new().thread_number(2).build()?;
+    let handle = pool.handle();
+    let values = (0..1000).collect::<Vec<u64>>();
+    let total = pool.spawn(move || sum(&handle, &values)).wait().unwrap();
+    assert_eq!(total, 499500);
+
+    let panic = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        pool.join(|| 1, || panic!("joined panic"))
+    }));
+    assert_eq!(panic.unwrap_err().downcast_ref::<&str>(), Some(&"joined panic"));
+
+    pool.shutdown();
+    Ok(())
+}
+
+#[test]
+fn par_iter() -> std::io::Result<()> {
+    use crate::iter::*;
+
+    let pool = ThreadPoolBuilder::new().thread_number(2).build()?;
+    let _enter = pool.enter();
+    let values = (0..1000u64).collect::<Vec<_>>();
+
+    let squares = pool.spawn(|| {
+        let squares = (0..1000u64).into_par_iter().map(|n| n * n).collect::<Vec<_>>();
+        let even = squares.par_iter().filter(|n| *n % 2 == 0).count();
+        (squares, even)
+    });
+    let (squares, even) = squares.wait().unwrap();
+    assert_eq!(squares, values.iter().map(|n| n * n).collect::<Vec<_>>());
+    assert_eq!(even, 500);
+
+    assert_eq!(values.par_iter().sum::<u64>(), 499500);
+    assert_eq!(values.par_iter().map(|n| *n).reduce(|| 0, u64::max), 999);
+    assert_eq!((-10..10i32).into_par_iter().with_min_len(4).sum::<i32>(), -10);
+
+    let total = std::sync::atomic::AtomicU64::new(0);
+    values.into_par_iter().for_each(|n| {
+        total.fetch_add(n, std::sync::atomic::Ordering::Relaxed);
+    });
+    assert_eq!(total.into_inner(), 499500);
+
+    pool.shutdown();
+    Ok(())
+}
+
+#[test]
+fn task_group() -> std::io::Result<()> {
+    use std::sync::{Arc, Barrier};
+    use std::time::Duration;
+
+    let pool = ThreadPoolBuilder::new().thread_number(2).build()?;
+    let mut group = pool.task_group();
+
+    // Results come in completion order.
+    group.spawn(|| {
+        std::thread::sleep(Duration::from_millis(100));
+        1
+    });
+    group.spawn(|| 2);
+    assert_eq!(group.join_next().unwrap().unwrap(), 2);
+    assert_eq!(group.join_next().unwrap().unwrap(), 1);
+    assert!(group.join_next().is_none());
+
+    group.spawn_async(async { 3 });
+    assert_eq!(pool.block_on(group.join_next_async()).unwrap().unwrap(), 3);
+
+    // Tasks which didn't start running are aborted, both by abort_all and on drop.
+    let barrier = Arc::new(Barrier::new(3));
+    let blockers = (0..2)
+        .map(|_| {
+            let barrier = Arc::clone(&barrier);
+            pool.spawn(move || { barrier.wait(); })
+        })
+        .collect::<Vec<_>>();
+    group.spawn(|| 4);
+    group.spawn(|| 5);
+    group.abort_all();
+    let results = group.join_all();
+    assert_eq!(results.len(), 2);
+    assert!(results.iter().all(|result| result.as_ref().unwrap_err().is_cancelled()));
+
+    let mut dropped = pool.task_group();
+    let aborted = dropped.spawn(|| 6);
+    drop(dropped);
+    assert!(!aborted.abort());
+
+    barrier.wait();
+    blockers.into_iter().for_each(|handle| handle.wait().unwrap());
+    pool.shutdown();
+    Ok(())
+}
+
+#[test]
+fn elastic_pool() -> std::io::Result<()> {
+    use std::sync::{atomic::{AtomicUsize, Ordering}, Arc, Barrier};
+    use std::time::{Duration, Instant};
+
+    let started = Arc::new(AtomicUsize::new(0));
+    let stopped = Arc::new(AtomicUsize::new(0));
+    let pool = {
+        let (started, stopped) = (Arc::clone(&started), Arc::clone(&stopped));
+        ThreadPoolBuilder::new()
+            .min_threads(1)
+            .max_threads(3)
+            .keep_alive(Duration::from_millis(100))
+            .on_start(move || { started.fetch_add(1, Ordering::SeqCst); })
+            .on_stop(move || { stopped.fetch_add(1, Ordering::SeqCst); })
+            .build()?
+    };
+    assert_eq!(pool.thread_count(), 1);
+
+    // Every task waits for the rest, so they only finish if the pool grows to run all of them.
+    let barrier = Arc::new(Barrier::new(3));
+    let handles = (0..3)
+        .map(|_| {
+            let barrier = Arc::clone(&barrier);
+            pool.spawn(move || { barrier.wait(); })
+        })
+        .collect::<Vec<_>>();
+    handles.into_iter().for_each(|handle| handle.wait().unwrap());
+    assert_eq!(pool.thread_count(), 3);
+    assert_eq!(started.load(Ordering::SeqCst), 3);
+
+    // The extra threads retire once the keep alive time elapses.
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while stopped.load(Ordering::SeqCst) < 2 && Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(pool.thread_count(), 1);
+    assert_eq!(stopped.load(Ordering::SeqCst), 2);
+
+    pool.shutdown();
+    assert_eq!(stopped.load(Ordering::SeqCst), 3);
+    Ok(())
+}
+
+#[test]
+fn set_thread_count() -> std::io::Result<()> {
+    use std::sync::{atomic::{AtomicUsize, Ordering}, Arc, Barrier};
+    use std::time::{Duration, Instant};
+
+    let stopped = Arc::new(AtomicUsize::new(0));
+    let pool = {
+        let stopped = Arc::clone(&stopped);
+        ThreadPoolBuilder::new()
+            .thread_number(1)
+            .on_stop(move || { stopped.fetch_add(1, Ordering::SeqCst); })
+            .build()?
+    };
+
+    pool.set_thread_count(3)?;
+    assert_eq!(pool.thread_count(), 3);
+
+    // Every task waits for the rest, so they only finish if the three threads run them.
+    let barrier = Arc::new(Barrier::new(3));
+    let handles = (0..3)
+        .map(|_| {
+            let barrier = Arc::clone(&barrier);
+            pool.spawn(move || { barrier.wait(); })
+        })
+        .collect::<Vec<_>>();
+    handles.into_iter().for_each(|handle| handle.wait().unwrap());
+
+    // A running task finishes before its thread exits.
+    let running = pool.spawn(|| std::thread::sleep(Duration::from_millis(50)));
+    pool.set_thread_count(1)?;
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while stopped.load(Ordering::SeqCst) < 2 && Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    assert!(running.wait().is_ok());
+    assert_eq!(pool.thread_count(), 1);
+    assert_eq!(stopped.load(Ordering::SeqCst), 2);
+    assert_eq!(pool.spawn(|| 1 + 1).wait().unwrap(), 2);
+
+    pool.shutdown();
+    assert_eq!(stopped.load(Ordering::SeqCst), 3);
+    Ok(())
+}
+
+#[test]
+fn metrics() -> std::io::Result<()> {
+    use crate::PoolMetrics;
+    use std::sync::{Arc, Barrier};
+    use std::time::{Duration, Instant};
+
+    let pool = ThreadPoolBuilder::new().thread_number(2).build()?;
+
+    // Outcomes are registered right after the output is sent, so they may lag behind it.
+    let wait_for = |condition: fn(&PoolMetrics) -> bool| {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !condition(&pool.metrics()) && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        pool.metrics()
+    };
+
+    let ok = pool.spawn(|| std::thread::sleep(Duration::from_millis(10)));
+    let panicked = pool.spawn(|| panic!("Metrics panic"));
+    ok.wait().unwrap();
+    assert!(panicked.wait().unwrap_err().is_panic());
+    wait_for(|metrics| metrics.busy_workers == 0);
+
+    // Keep both workers busy, so the next task stays queued until it's aborted.
+    let barrier = Arc::new(Barrier::new(3));
+    let blockers = (0..2)
+        .map(|_| {
+            let barrier = Arc::clone(&barrier);
+            pool.spawn(move || { barrier.wait(); })
+        })
+        .collect::<Vec<_>>();
+    wait_for(|metrics| metrics.busy_workers == 2);
+
+    let aborted = pool.spawn(|| ());
+    aborted.abort();
+    let periodic = pool.periodic(|| (), Duration::from_secs(60), None);
+
+    let metrics = pool.metrics();
+    assert_eq!(metrics.spawned, 5);
+    assert_eq!(metrics.completed, 1);
+    assert_eq!(metrics.panicked, 1);
+    assert_eq!(metrics.queue_depth, 1);
+    assert_eq!(metrics.periodic_tasks, 1);
+    assert_eq!(metrics.workers.len(), 2);
+
+    barrier.wait();
+    blockers.into_iter().for_each(|handle| handle.wait().unwrap());
+    periodic.cancel();
+
+    // The aborted task is counted once a worker takes it out of the queue.
+    let metrics = wait_for(|metrics| metrics.cancelled == 1 && metrics.busy_workers == 0);
+    assert_eq!(metrics.completed, 3);
+    assert_eq!(metrics.cancelled, 1);
+    assert_eq!(metrics.queue_depth, 0);
+    assert_eq!(metrics.idle_workers, 2);
+    assert_eq!(metrics.workers.iter().map(|worker| worker.tasks).sum::<usize>(), 5);
+    assert!(metrics.workers.iter().any(|worker| worker.busy_time >= Duration::from_millis(10)));
+
+    pool.shutdown();
+    Ok(())
+}
+
+#[cfg(feature = "metrics")]
+#[test]
+fn prometheus() -> std::io::Result<()> {
+    use std::io::{Read, Write};
+
+    let pool = ThreadPoolBuilder::new()
+        .thread_number(1)
+        .pool_name("exported \"pool\"")
+        .build()?;
+    pool.spawn(|| std::thread::sleep(std::time::Duration::from_millis(2))).wait().unwrap();
+
+    // A client which never sends its request doesn't hold back the rest.
+    let server = pool.serve_metrics("0.0.0.0:0")?;
+    let addr = ("127.0.0.1", server.local_addr().port());
+    let _stalled = std::net::TcpStream::connect(addr)?;
+    let mut stream = std::net::TcpStream::connect(addr)?;
+    stream.write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    server.shutdown();
+
+    let label = "pool=\"exported \\\"pool\\\"\"";
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.contains("# TYPE fast_pool_tasks_spawned_total counter\n"));
+    assert!(response.contains(&format!("fast_pool_tasks_spawned_total{{{}}} 1\n", label)));
+    assert!(response.contains(&format!("fast_pool_queue_depth{{{}}} 0\n", label)));
+    assert!(response.contains("# TYPE fast_pool_task_run_seconds histogram\n"));
+    let bucket = format!("fast_pool_task_run_seconds_bucket{{{},le=\"0.001\"}} 0\n", label);
+    assert!(response.contains(&bucket));
+    assert!(response.contains(&format!("fast_pool_task_run_seconds_count{{{}}} 1\n", label)));
+
+    let body = response.split("\r\n\r\n").nth(1).unwrap();
+    assert_eq!(pool.render_metrics().lines().count(), body.lines().count());
+
+    pool.shutdown();
+    Ok(())
+}
+
+#[cfg(feature = "tracing")]
+#[test]
+fn tracing_spans() -> std::io::Result<()> {
+    use std::cell::RefCell;
+    use std::sync::Mutex;
+    use tracing::dispatcher::DefaultGuard;
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Metadata, Subscriber};
+    use tracing_core::span::Current;
+
+    thread_local! {
+        static ENTERED: RefCell<Vec<Id>> = const { RefCell::new(Vec::new()) };
+        static DEFAULT: RefCell<Option<DefaultGuard>> = const { RefCell::new(None) };
+    }
+
+    /// Records the explicit parents and the fields of the spans created.
+    #[derive(Default)]
+    struct Recorder {
+        spans: Mutex<Vec<(&'static Metadata<'static>, Option<u64>, String)>>,
+    }
+
+    struct Fields<'a>(&'a mut String);
+
+    impl tracing::field::Visit for Fields<'_> {
+        fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+            self.0.push_str(&format!("{}={:?};", field.name(), value));
+        }
+    }
+
+    impl Subscriber for Recorder {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            let mut fields = String::new();
+            span.record(&mut Fields(&mut fields));
+            let mut spans = self.spans.lock().unwrap();
+            spans.push((span.metadata(), span.parent().map(Id::into_u64), fields));
+            Id::from_u64(spans.len() as u64)
+        }
+
+        fn record(&self, span: &Id, values: &Record<'_>) {
+            let mut spans = self.spans.lock().unwrap();
+            values.record(&mut Fields(&mut spans[span.into_u64() as usize - 1].2));
+        }
+
+        // Needed for the pool to capture the span which is current when a task is spawned.
+        fn current_span(&self) -> Current {
+            match ENTERED.with(|entered| entered.borrow().last().cloned()) {
+                Some(id) => {
+                    let metadata = self.spans.lock().unwrap()[id.into_u64() as usize - 1].0;
+                    Current::new(id, metadata)
+                }
+                None => Current::none(),
+            }
+        }
+
+        fn enter(&self, span: &Id) {
+            ENTERED.with(|entered| entered.borrow_mut().push(span.clone()));
+        }
+
+        fn exit(&self, _: &Id) {
+            ENTERED.with(|entered| entered.borrow_mut().pop());
+        }
+
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+        fn event(&self, _: &Event<'_>) {}
+    }
+
+    let dispatch = tracing::Dispatch::new(Recorder::default());
+    let pool = {
+        let dispatch = dispatch.clone();
+        ThreadPoolBuilder::new()
+            .thread_number(1)
+            .pool_name("traced")
+            // The workers keep the recorder as their default until they stop.
+            .on_start(move || {
+                let guard = tracing::dispatcher::set_default(&dispatch);
+                DEFAULT.with(|default| *default.borrow_mut() = Some(guard));
+            })
+            .on_stop(|| DEFAULT.with(|default| drop(default.borrow_mut().take())))
+            .build()?
+    };
+
+    tracing::dispatcher::with_default(&dispatch, || {
+        let request = tracing::info_span!("request");
+        let _entered = request.enter();
+        pool.spawn(|| ()).wait().unwrap();
+    });
+    // The run time is recorded after the output is sent, so wait for the worker to finish.
+    pool.shutdown();
+
+    let recorder = dispatch.downcast_ref::<Recorder>().unwrap();
+    let spans = recorder.spans.lock().unwrap();
+    assert_eq!(spans.len(), 2);
+    // The span of the task is a child of the one which was current when it was spawned.
+    assert_eq!(spans[1].1, Some(1));
+    let fields = &spans[1].2;
+    assert_eq!(spans[1].0.name(), "task");
+    assert!(fields.starts_with("task.id="));
+    assert!(fields.contains("pool=\"traced\";worker=0;"));
+    assert!(fields.contains(&format!("spawned_at={}:", file!())));
+    assert!(fields.contains("queue_wait=") && fields.contains("run_time="));
+    Ok(())
+}
+
+#[test]
+fn panic_handler() -> std::io::Result<()> {
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+
+    let panics = Arc::new(Mutex::new(Vec::new()));
+    let pool = {
+        let panics = Arc::clone(&panics);
+        ThreadPoolBuilder::new()
+            .thread_number(2)
+            .panic_handler(move |panic| {
+                let name = panic.task_name().map(String::from);
+                let message = panic.message().map(String::from);
+                panics.lock().unwrap().push((name, message, panic.worker()));
+            })
+            .build()?
+    };
+
+    pool.task_builder().name("detached").spawn_detached(|| panic!("Detached panic"));
+    pool.task_builder()
+        .name("periodic")
+        .periodic(|| panic!("Periodic panic"), Duration::from_millis(10), Some(1));
+    let error = pool.spawn_async(async { panic!("Async panic") }).wait().unwrap_err();
+    assert_eq!(error.panic_message(), Some("Async panic"));
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while panics.lock().unwrap().len() < 3 && Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(10));
+    }
+
+    let mut panics = std::mem::take(&mut *panics.lock().unwrap());
+    panics.sort();
+    assert_eq!(panics.len(), 3);
+    assert_eq!(panics[0].0, None);
+    assert_eq!(panics[0].1.as_deref(), Some("Async panic"));
+    assert_eq!(panics[1].0.as_deref(), Some("detached"));
+    assert_eq!(panics[1].1.as_deref(), Some("Detached panic"));
+    assert_eq!(panics[2].0.as_deref(), Some("periodic"));
+    assert!(panics.iter().all(|(_, _, worker)| matches!(worker, Some(0 | 1))));
+    pool.shutdown();
+
+    // The pool shuts itself down on the first panic.
+    let pool = ThreadPoolBuilder::new()
+        .thread_number(1)
+        .panic_policy(PanicPolicy::Shutdown)
+        .build()?;
+    assert!(pool.spawn(|| panic!("Fatal panic")).wait().unwrap_err().is_panic());
+    assert!(matches!(pool.try_spawn(|| ()), Err(SpawnError::Shutdown(_))));
+    pool.shutdown();
+    Ok(())
+}
+
+#[test]
+fn worker_hooks() -> std::io::Result<()> {
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+
+    let started = Arc::new(Mutex::new(Vec::new()));
+    let before = Arc::new(Mutex::new(Vec::new()));
+    let after = Arc::new(Mutex::new(Vec::new()));
+    let pool = {
+        let (started, before, after) = (started.clone(), before.clone(), after.clone());
+        ThreadPoolBuilder::new()
+            .thread_number(2)
+            .pool_name("hooks")
+            .on_start_with(move |worker| {
+                assert_eq!(worker.thread_id(), std::thread::current().id());
+                started.lock().unwrap().push((worker.index(), worker.pool_name().to_string()));
+            })
+            .before_with(move |_, task| {
+                assert!(task.elapsed().is_none());
+                before.lock().unwrap().push(task.id());
+            })
+            .after_with(move |worker, task| {
+                assert!(worker.index() < 2);
+                let name = task.name().map(String::from);
+                after.lock().unwrap().push((task.id(), name, task.kind(), task.elapsed().unwrap()));
+            })
+            .build()?
+    };
+
+    pool.task_builder()
+        .name("sleepy")
+        .spawn(|| std::thread::sleep(Duration::from_millis(10)))
+        .wait()
+        .unwrap();
+    pool.spawn_async(async {}).wait().unwrap();
+
+    // The hooks after the tasks run once their outputs were sent.
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while after.lock().unwrap().len() < 2 && Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    pool.shutdown();
+
+    let mut started = std::mem::take(&mut *started.lock().unwrap());
+    started.sort();
+    assert_eq!(started, vec![(0, String::from("hooks")), (1, String::from("hooks"))]);
+
+    // Both workers may record their tasks in any order, so they are looked up by kind.
+    let after = std::mem::take(&mut *after.lock().unwrap());
+    let find = |kind| after.iter().find(|(.., task_kind, _)| *task_kind == kind).unwrap();
+    assert_eq!(after.len(), 2);
+
+    let (sync_id, name, _, elapsed) = find(TaskKind::Sync);
+    assert_eq!(name.as_deref(), Some("sleepy"));
+    assert!(*elapsed >= Duration::from_millis(10));
+    let (async_id, name, ..) = find(TaskKind::Async);
+    assert_eq!(name.as_deref(), None);
+
+    let mut before = std::mem::take(&mut *before.lock().unwrap());
+    before.sort();
+    assert_eq!(before, vec![*sync_id, *async_id]);
+    Ok(())
+}
+
+#[test]
+fn nested_hooks() -> std::io::Result<()> {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let (before, after) = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0)));
+    let pool = {
+        let (before, after) = (Arc::clone(&before), Arc::clone(&after));
+        ThreadPoolBuilder::new()
+            .thread_number(1)
+            .before(move || {
+                before.fetch_add(1, Ordering::Relaxed);
+            })
+            .after(move || {
+                after.fetch_add(1, Ordering::Relaxed);
+            })
+            .build()?
+    };
+
+    // The only worker runs the scoped tasks itself while it waits for them.
+    let handle = pool.handle();
+    pool.spawn(move || {
+        handle.scope(|s| {
+            for _ in 0..3 {
+                s.spawn(|| ());
+            }
+        })
+    })
+    .wait()
+    .unwrap();
+
+    let metrics = pool.metrics();
+    pool.shutdown();
+
+    assert_eq!(before.load(Ordering::Relaxed), 4);
+    assert_eq!(after.load(Ordering::Relaxed), 4);
+    assert!(metrics.workers[0].tasks >= 3);
+    Ok(())
+}
+
+#[test]
+fn enter() -> std::io::Result<()> {
+    let first = ThreadPoolBuilder::new().thread_number(1).pool_name("first").build()?;
+    let second = ThreadPoolBuilder::new().thread_number(1).pool_name("second").build()?;
+    assert!(Handle::try_get().is_none());
+
+    {
+        let _first = first.enter();
+        assert_eq!(Handle::current().pool_name(), "first");
+
+        {
+            let _second = second.enter();
+            assert_eq!(Handle::current().pool_name(), "second");
+            // Workers see their own pool, whatever the spawning thread entered.
+            let name = crate::spawn(|| Handle::current().pool_name().to_string());
+            assert_eq!(name.wait().unwrap(), "second");
+        }
+
+        assert_eq!(Handle::current().pool_name(), "first");
+        // Other threads don't see the pool entered by this one.
+        let other = std::thread::spawn(|| Handle::try_get().is_none());
+        assert!(other.join().unwrap());
+    }
+
+    assert!(crate::try_spawn(|| ()).unwrap_err().is_no_pool());
+    first.shutdown();
+    second.shutdown();
+    Ok(())
+}
+
+#[test]
+fn pool_timer() -> std::io::Result<()> {
+    use std::time::{Duration, Instant};
+
+    // A pool whose workers can't start stops its timer again.
+    assert!(ThreadPoolBuilder::new().thread_stack_size(usize::MAX).build().is_err());
+
+    let pool = ThreadPoolBuilder::new().thread_number(1).timer(false).build()?;
+    let error = pool.try_periodic(|| (), Duration::from_millis(10), None).unwrap_err();
+    assert!(error.is_no_timer());
+    let error = pool.try_spawn_at(Instant::now(), || ()).unwrap_err();
+    assert!(error.is_no_timer());
+    pool.shutdown();
+
+    let pool = ThreadPoolBuilder::new().thread_number(1).build()?;
+    let periodic = pool.periodic(|| (), Duration::from_millis(10), None);
+    let delayed = pool.spawn_after(Duration::from_secs(60), || ());
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while periodic.run_count() == 0 && Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    assert!(periodic.run_count() > 0);
+
+    // Shutting down the pool stops its timer, which drops the tasks waiting in it.
+    pool.shutdown();
+    assert_eq!(periodic.next_run(), None);
+    assert!(delayed.wait().unwrap_err().is_shutdown());
+
+    // The timer of the current pool can still be stopped on its own.
+    let pool = ThreadPoolBuilder::new().thread_number(1).build()?;
+    let delayed = pool.spawn_after(Duration::from_secs(60), || ());
+    {
+        let _enter = pool.enter();
+        #[allow(deprecated)]
+        crate::shutdown_timer();
+    }
+    assert!(delayed.wait().unwrap_err().is_shutdown());
+    assert_eq!(pool.spawn(|| 1).wait().unwrap(), 1);
+    pool.shutdown();
+    Ok(())
+}
+
+#[test]
+fn join_full_queue() -> std::io::Result<()> {
+    use crate::iter::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Barrier};
+
+    let policies = [RejectionPolicy::Error, RejectionPolicy::DropNew, RejectionPolicy::DropOldest];
+
+    for policy in policies {
+        let after = Arc::new(AtomicUsize::new(0));
+        let pool = {
+            let after = Arc::clone(&after);
+            ThreadPoolBuilder::new()
+                .thread_number(1)
+                .queue_capacity(1)
+                .rejection_policy(policy)
+                .after(move || {
+                    after.fetch_add(1, Ordering::Relaxed);
+                })
+                .build()?
+        };
+
+        // Keep the only worker busy and the queue full.
+        let (started, release) = (Barrier::new(2), Barrier::new(2));
+        pool.scope(|s| {
+            s.spawn(|| { started.wait(); release.wait(); });
+            started.wait();
+            s.spawn(|| ());
+
+            // Both functions run here, without waiting for the worker to reach the queued one.
+            assert_eq!(pool.join(|| 1, || 2), (1, 2));
+            let _enter = pool.enter();
+            let values = (0..100u64).collect::<Vec<_>>();
+            assert_eq!(values.par_iter().sum::<u64>(), 4950);
+            // The queued halves which ran here gave their places back.
+            assert_eq!(pool.metrics().queue_depth, 1);
+            release.wait();
+        });
+
+        let handle = pool.handle();
+        pool.shutdown();
+        assert_eq!(handle.join(|| 1, || 2), (1, 2));
+
+        // The queued halves are dropped without being counted nor calling the hooks.
+        let metrics = handle.metrics();
+        assert_eq!((metrics.spawned, metrics.completed, metrics.queue_depth), (2, 2, 0));
+        assert_eq!(after.load(Ordering::Relaxed), 2);
+    }
+
+    Ok(())
+}
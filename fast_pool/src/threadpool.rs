@@ -1,5 +1,11 @@
-use crate::{builder::ThreadPoolBuilder, handle::Handle, shared::Shared, worker::Worker};
-use std::{collections::VecDeque, sync::Arc};
+use crate::{
+    builder::ThreadPoolBuilder,
+    handle::{Handle, ShutdownReport},
+    shared::{PoolSize, Shared},
+    timer::TimerHandle,
+    worker::WorkerConfig,
+};
+use std::time::Duration;
 
 /// The thread pool used to execute tasks.
 pub struct ThreadPool {
@@ -14,35 +20,48 @@ impl ThreadPool {
     }
 
     pub(crate) fn start(builder: ThreadPoolBuilder) -> std::io::Result<Self> {
-        let shared = Shared::new();
-        let mut handles = VecDeque::new();
+        let min_threads = builder.min_threads.unwrap_or(builder.thread_number);
+        let max_threads = builder.max_threads
+            .unwrap_or(builder.thread_number)
+            .max(min_threads)
+            .max(1);
 
-        use std::thread::Builder;
-        for _ in 0..builder.thread_number {
-            let worker = Worker::new(
-                Arc::clone(&shared),
-                builder.before.as_ref().map(Arc::clone),
-                builder.after.as_ref().map(Arc::clone),
-                builder.on_start.as_ref().map(Arc::clone),
-                builder.on_stop.as_ref().map(Arc::clone)
-            );
+        let config = WorkerConfig {
+            before: builder.before,
+            after: builder.after,
+            on_start: builder.on_start,
+            on_stop: builder.on_stop,
+            name: builder.name,
+            stack_size: builder.stack_size,
+            panic_handler: builder.panic_handler,
+            panic_policy: builder.panic_policy,
+        };
+        let size = PoolSize {
+            min_threads,
+            max_threads,
+            keep_alive: builder.keep_alive,
+        };
+        let timer = match builder.timer {
+            true => Some(TimerHandle::new(format!("{}-timer", builder.pool_name))?),
+            false => None,
+        };
+        let shared = Shared::new(
+            builder.pool_name,
+            timer,
+            config,
+            size,
+            builder.queue_capacity,
+            builder.rejection_policy
+        );
 
-            let mut thread_builder = Builder::new();
+        let handle = Handle::new(shared);
 
-            if let Some(size) = &builder.stack_size {
-                thread_builder = thread_builder.stack_size(*size);
-            }
-
-            let handle = thread_builder
-                .name((builder.name)())
-                .spawn(move || worker.run())?;
-
-            handles.push_back(handle);
+        // Nothing else would ever stop the timer and the workers which already started.
+        if let Err(error) = handle.shared.start() {
+            handle.shutdown();
+            return Err(error);
         }
 
-        let handle = Handle::new(shared, handles);
-        crate::context::set_handle(handle.clone());
-
         Ok(Self { handle })
     }
     /// Returns a reference to the current [handle](Handle).
@@ -59,6 +78,12 @@ impl ThreadPool {
     pub fn shutdown(self) {
         self.handle.shutdown()
     }
+
+    /// Shuts down the thread pool gracefully, see
+    /// [shutdown_graceful](Handle::shutdown_graceful) for more information.
+    pub fn shutdown_graceful(self, timeout: Duration) -> ShutdownReport {
+        self.handle.shutdown_graceful(timeout)
+    }
 }
 
 impl std::ops::Deref for ThreadPool {
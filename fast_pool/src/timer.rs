@@ -1,52 +1,124 @@
-use std::time::Duration;
-use crate::task::PeriodicTask;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use crate::task::{DelayedTask, PeriodicTask};
 use crossbeam_channel::{unbounded, Sender, Receiver, RecvTimeoutError};
+use parking_lot::Mutex;
+
+/// A task handled by the timer.
+pub enum TimerTask {
+    Periodic(PeriodicTask),
+    Delayed(DelayedTask)
+}
+
+impl TimerTask {
+    fn can_run(&self) -> bool {
+        match self {
+            Self::Periodic(task) => task.can_run(),
+            Self::Delayed(task) => task.can_run()
+        }
+    }
+
+    fn is_cancelled(&self) -> bool {
+        match self {
+            Self::Periodic(task) => task.is_cancelled(),
+            Self::Delayed(_) => false
+        }
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        match self {
+            Self::Periodic(task) => task.deadline(),
+            Self::Delayed(task) => Some(task.deadline())
+        }
+    }
+
+    fn schedule(self) {
+        match self {
+            Self::Periodic(task) => task.schedule(),
+            Self::Delayed(task) => task.schedule()
+        }
+    }
+}
 
 pub enum TimerAction {
-    Schedule(PeriodicTask),
+    Schedule(TimerTask),
+    /// Makes the timer check again when the next task is due.
+    Wake,
     Abort
 }
 
 enum RecvResult {
     Abort,
-    Exit,
     Continue
 }
 
 pub struct Timer {
-    tasks: Vec<PeriodicTask>,
+    tasks: Vec<TimerTask>,
     receiver: Receiver<TimerAction>,
     times: [u64; 34],
     sleep: u8
 }
 
-#[derive(Clone)]
+/// The timer of a pool, which queues its delayed and periodic tasks once they are due. It runs
+/// on its own thread until the pool shuts down.
 pub struct TimerHandle {
-    sender: Sender<TimerAction>
+    sender: Sender<TimerAction>,
+    thread: Mutex<Option<JoinHandle<usize>>>
 }
 
 impl TimerHandle {
     pub fn schedule(&self, task: PeriodicTask) {
-        let _ = self.sender.send(TimerAction::Schedule(task));
+        let _ = self.sender.send(TimerAction::Schedule(TimerTask::Periodic(task)));
     }
 
-    pub fn shutdown(self) {
+    pub fn schedule_delayed(&self, task: DelayedTask) {
+        let _ = self.sender.send(TimerAction::Schedule(TimerTask::Delayed(task)));
+    }
+
+    pub fn waker(&self) -> TimerWaker {
+        TimerWaker(self.sender.clone())
+    }
+
+    /// Makes the timer exit, dropping the tasks waiting in it, which notifies their handles.
+    pub fn stop(&self) {
         let _ = self.sender.send(TimerAction::Abort);
     }
+
+    /// Stops the timer, waiting for its thread to exit, returning how many delayed tasks were
+    /// dropped, which is only known the first time.
+    pub fn shutdown(&self) -> usize {
+        self.stop();
+
+        match self.thread.lock().take() {
+            Some(thread) => thread.join().unwrap_or(0),
+            None => 0,
+        }
+    }
+}
+
+/// Wakes up the timer of a pool, so a task which becomes due without being handed to it, like a
+/// resumed periodic task, doesn't wait for the timer to poll again.
+#[derive(Clone)]
+pub struct TimerWaker(Sender<TimerAction>);
+
+impl TimerWaker {
+    pub fn wake(&self) {
+        let _ = self.0.send(TimerAction::Wake);
+    }
 }
 
 impl TimerHandle {
-    pub fn new() -> std::io::Result<Self> {
-        Timer::init()
+    pub fn new(name: String) -> std::io::Result<Self> {
+        Timer::init(name)
     }
 }
 
 impl Timer {
-    pub fn init() -> std::io::Result<TimerHandle> {
+    pub fn init(name: String) -> std::io::Result<TimerHandle> {
         let (tx, rx) = unbounded();
 
-        std::thread::Builder::new()
-            .name("fast_pool-timer".to_string())
+        let thread = std::thread::Builder::new()
+            .name(name)
             .spawn(move || {
                 Self {
                     tasks: Vec::new(),
@@ -58,27 +130,35 @@ impl Timer {
                         1900, 2000, 2100, 2200, 2300, 2400, 2500
                     ],
                     sleep: 0
-                }.run();
+                }.run()
             })?;
 
         Ok(TimerHandle {
-            sender: tx
+            sender: tx,
+            thread: Mutex::new(Some(thread))
         })
     }
 
     fn try_recv_timeout(&mut self) -> RecvResult {
+        // Once idle for long, keep polling at the longest interval.
         if self.sleep == self.times.len() as u8 {
-            self.sleep = 0;
-            return RecvResult::Exit;
+            self.sleep -= 1;
+        }
+
+        // Don't sleep past the closest deadline, so tasks run as soon as they are due.
+        let mut timeout = Duration::from_millis(self.times[self.sleep as usize]);
+        if let Some(deadline) = self.tasks.iter().filter_map(TimerTask::deadline).min() {
+            timeout = timeout.min(deadline.saturating_duration_since(Instant::now()));
         }
 
-        match self.receiver.recv_timeout(Duration::from_millis(self.times[self.sleep as usize])) {
+        match self.receiver.recv_timeout(timeout) {
             Ok(action) => {
                 match action {
                     TimerAction::Schedule(task) => {
                         self.tasks.push(task);
                         self.sleep = 0;
                     },
+                    TimerAction::Wake => self.sleep = 0,
                     TimerAction::Abort => return RecvResult::Abort
                 }
             },
@@ -92,22 +172,34 @@ impl Timer {
     }
 
     fn schedule_available(&mut self) {
+        // Cancelled tasks are just dropped.
+        self.tasks.retain(|task| !task.is_cancelled());
+
         for task in self.tasks.drain_filter(|task| task.can_run()) {
             task.schedule();
         }
     }
 
-    fn run(mut self) {
+    /// Runs the timer until it is stopped, returning how many delayed tasks it drops.
+    fn run(mut self) -> usize {
         loop {
             self.schedule_available();
 
-            match self.try_recv_timeout() {
-                RecvResult::Abort => break,
-                RecvResult::Exit if self.tasks.is_empty() => break,
-                _ => ()
+            if let RecvResult::Abort = self.try_recv_timeout() {
+                break;
             }
         }
 
-        crate::context::delete_timer();
+        // Tasks sent before the timer stopped are dropped along with the ones waiting in it.
+        let sent = self.receiver.try_iter().filter_map(|action| match action {
+            TimerAction::Schedule(task) => Some(task),
+            TimerAction::Wake | TimerAction::Abort => None,
+        });
+        self.tasks.extend(sent);
+
+        self.tasks
+            .iter()
+            .filter(|task| matches!(task, TimerTask::Delayed(_)))
+            .count()
     }
 }
@@ -1,68 +1,257 @@
-use crate::{builder::HookFn, shared::Shared, task::TaskType};
-use std::sync::Arc;
+use crate::{
+    builder::{HookFn, NameFn, PanicFn, PanicPolicy, TaskHookFn},
+    handle::Handle,
+    hook::WorkerContext,
+    metrics::WorkerStats,
+    shared::Shared,
+    task::TaskType,
+};
+use crossbeam_deque::Worker as LocalQueue;
+use std::{
+    cell::{Cell, RefCell},
+    sync::Arc,
+    thread::{Builder, JoinHandle},
+    time::{Duration, Instant},
+};
+
+thread_local! {
+    /// The local queue of the worker running on this thread, if any.
+    static LOCAL: RefCell<Option<Local>> = const { RefCell::new(None) };
+}
+
+/// The data of a worker which is only accessible from its own thread.
+struct Local {
+    /// The pool the worker belongs to.
+    shared: Arc<Shared>,
+    /// The queue of the worker.
+    queue: LocalQueue<TaskType>,
+    /// The position of the worker inside the pool.
+    index: usize,
+    /// The number of tasks looked for by the worker, used to avoid starving low priority tasks.
+    tick: Cell<usize>,
+    /// The statistics of the worker.
+    stats: Arc<WorkerStats>,
+}
+
+impl Local {
+    fn find_task(&self) -> Option<TaskType> {
+        self.shared.find_task(&self.queue, self.index, self.next_tick())
+    }
+
+    fn next_tick(&self) -> usize {
+        let tick = self.tick.get().wrapping_add(1);
+        self.tick.set(tick);
+        tick
+    }
+}
+
+/// Pushes the task into the local queue of the current thread if it is a worker of the given
+/// pool, otherwise the task is given back.
+pub fn push_local(shared: &Shared, task: TaskType) -> Result<(), TaskType> {
+    LOCAL.with(|local| match &*local.borrow() {
+        Some(local) if std::ptr::eq(Arc::as_ptr(&local.shared), shared) => {
+            local.queue.push(task);
+            Ok(())
+        }
+        _ => Err(task),
+    })
+}
+
+/// Whether the current thread is a worker of the given pool.
+pub fn is_worker_of(shared: &Shared) -> bool {
+    LOCAL.with(|local| {
+        matches!(&*local.borrow(), Some(local) if std::ptr::eq(Arc::as_ptr(&local.shared), shared))
+    })
+}
+
+/// Runs one of the queued tasks of the given pool if the current thread is one of its workers,
+/// returning whether a task was ran. This allows workers to keep doing work while they wait.
+pub fn run_pending(shared: &Shared) -> bool {
+    let task = LOCAL.with(|local| match &*local.borrow() {
+        Some(local) if std::ptr::eq(Arc::as_ptr(&local.shared), shared) => local
+            .find_task()
+            .map(|task| (task, local.index, Arc::clone(&local.stats))),
+        _ => None,
+    });
+
+    match task {
+        Some((task, index, stats)) => {
+            let context = WorkerContext::new(index, shared.name());
+            run_task(shared, &context, &stats, task, true);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Runs a task taken from the queues by a worker, calling the hooks of the pool around it and
+/// registering it in the stats of the worker. Nested tasks are the ones ran while the worker
+/// waits inside another task, the worker is already busy and their time is accounted there.
+fn run_task(
+    shared: &Shared,
+    context: &WorkerContext<'_>,
+    stats: &WorkerStats,
+    task: TaskType,
+    nested: bool
+) {
+    let config = shared.config();
+    // The task is only described when there is a hook to give it to.
+    let mut info = (config.before.is_some() || config.after.is_some()).then(|| task.info());
+
+    if let (Some(before), Some(info)) = (&config.before, &info) {
+        (before)(context, info);
+    }
+
+    if !nested {
+        stats.set_busy(true);
+    }
+
+    let run = run_traced(shared, context.index(), task);
+
+    if nested {
+        stats.ran(None);
+    } else {
+        stats.ran(Some(run));
+        stats.set_busy(false);
+    }
+
+    if let (Some(after), Some(info)) = (&config.after, &mut info) {
+        info.elapsed = Some(run);
+        (after)(context, info);
+    }
+}
+
+/// Runs a task taken from the queues by the worker with the given index, registering its
+/// outcome and how long it waited and ran, which is returned. With the `tracing` feature the
+/// task runs inside its own span.
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+fn run_traced(shared: &Shared, index: usize, task: TaskType) -> Duration {
+    let wait = task.queued_at().elapsed();
+
+    #[cfg(feature = "tracing")]
+    let span = task.meta().span(shared.name(), index, wait);
+    #[cfg(feature = "tracing")]
+    let _entered = span.enter();
+
+    let start = Instant::now();
+    let outcome = task.run(shared, Some(index));
+    let run = start.elapsed();
+
+    #[cfg(feature = "tracing")]
+    span.record("run_time", tracing::field::debug(run));
+
+    shared.counters.timed(wait, run);
+    shared.counters.ran(outcome);
+    run
+}
 
 pub enum WorkerAction {
     Run(TaskType),
-    Retry,
     Exit,
 }
 
-/// A worker of the thread pool.
-pub struct Worker {
-    /// The data shared between all workers.
-    shared: Arc<Shared>,
+/// The configuration used to spawn the workers of the pool.
+pub struct WorkerConfig {
     /// The function executed before every task.
-    before: Option<Arc<HookFn>>,
+    pub before: Option<Arc<TaskHookFn>>,
     /// The function executed after every task.
-    after: Option<Arc<HookFn>>,
+    pub after: Option<Arc<TaskHookFn>>,
     /// The function executed at thread creation.
-    on_start: Option<Arc<HookFn>>,
+    pub on_start: Option<Arc<HookFn>>,
     /// The function executed just before exiting the thread.
-    on_stop: Option<Arc<HookFn>>,
+    pub on_stop: Option<Arc<HookFn>>,
+    /// The function used to name the threads.
+    pub name: Arc<NameFn>,
+    /// The stack size of the threads.
+    pub stack_size: Option<usize>,
+    /// The function called when a task panics.
+    pub panic_handler: Option<Arc<PanicFn>>,
+    /// What to do when a task panics.
+    pub panic_policy: PanicPolicy,
+}
+
+/// A worker of the thread pool.
+pub struct Worker {
+    /// The data shared between all workers.
+    shared: Arc<Shared>,
+    /// The position of the worker inside the pool.
+    index: usize,
+    /// The statistics of the worker, shared with the pool.
+    stats: Arc<WorkerStats>,
 }
 
 impl Worker {
-    pub fn new(
-        shared: Arc<Shared>,
-        before: Option<Arc<HookFn>>,
-        after: Option<Arc<HookFn>>,
-        on_start: Option<Arc<HookFn>>,
-        on_stop: Option<Arc<HookFn>>,
-    ) -> Self {
+    pub fn new(shared: Arc<Shared>, index: usize, stats: Arc<WorkerStats>) -> Self {
         Self {
             shared,
-            before,
-            after,
-            on_start,
-            on_stop
+            index,
+            stats,
         }
     }
 
-    pub fn run(self) {
-        if let Some(fun) = self.on_start {
-            (fun)();
+    /// Spawns a new thread running the worker.
+    pub fn spawn(self, queue: LocalQueue<TaskType>) -> std::io::Result<JoinHandle<()>> {
+        let config = self.shared.config();
+        let mut builder = Builder::new().name((config.name)());
+
+        if let Some(size) = config.stack_size {
+            builder = builder.stack_size(size);
         }
 
-        loop {
-            match self.shared.wait() {
-                WorkerAction::Run(task) => {
-                    if let Some(before) = &self.before {
-                        (before)();
-                    }
+        builder.spawn(move || self.run(queue))
+    }
+
+    /// Waits for the next action, borrowing the local queue from the thread local storage.
+    fn next_action(&self) -> WorkerAction {
+        LOCAL.with(|local| {
+            let local = local.borrow();
+            let local = local.as_ref().expect("Worker local queue not initialized");
+            self.shared.wait(&local.queue, self.index, local.next_tick())
+        })
+    }
+
+    /// Runs the worker, the given queue is moved into the thread local storage so tasks spawned
+    /// from this thread can be pushed into it.
+    fn run(self, queue: LocalQueue<TaskType>) {
+        LOCAL.with(|local| {
+            *local.borrow_mut() = Some(Local {
+                shared: Arc::clone(&self.shared),
+                queue,
+                index: self.index,
+                tick: Cell::new(0),
+                stats: Arc::clone(&self.stats),
+            });
+        });
+
+        // Every worker sees its own pool as the current one.
+        let _enter = Handle::new(Arc::clone(&self.shared)).enter();
+        let config = self.shared.config();
+        let context = WorkerContext::new(self.index, self.shared.name());
 
-                    task.run();
+        if let Some(fun) = &config.on_start {
+            (fun)(&context);
+        }
 
-                    if let Some(after) = &self.after {
-                        (after)();
-                    }
+        while let WorkerAction::Run(task) = self.next_action() {
+            run_task(&self.shared, &context, &self.stats, task, false);
+        }
+
+        // Give back any task left in the local queue, so it is handled by the rest of the workers
+        // if this one was retired, or along with the rest of the remaining tasks on shutdown.
+        if let Some(local) = LOCAL.with(|local| local.borrow_mut().take()) {
+            if !local.queue.is_empty() {
+                while let Some(task) = local.queue.pop() {
+                    self.shared.push_global(task);
                 }
-                WorkerAction::Retry => (),
-                WorkerAction::Exit => break,
+
+                self.shared.notify();
             }
         }
 
-        if let Some(fun) = self.on_stop {
-            (fun)();
+        if let Some(fun) = &config.on_stop {
+            (fun)(&context);
         }
+
+        self.shared.worker_exited();
     }
 }